@@ -1,6 +1,6 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 
 fn get_letter_value(c: char) -> u32 {
@@ -24,67 +24,169 @@ fn read_file() -> BufReader<File> {
 }
 
 
-fn part1() {
-    let reader = read_file();
-    let mut result = 0;
-    for line in reader.lines() {
-        let line = line.unwrap();
-        let mut tracker: HashMap<char, [usize; 2]> = HashMap::new();
-        let mut n_elements: usize = 0;
-        for (i, c) in line.chars().enumerate() {
-            let indexes = tracker.entry(c).or_insert([i, i]);
-            indexes[1] = i;
-            n_elements += 1;
-        }
-        result += get_letter_value(get_repeated(tracker, n_elements));
+fn read_lines() -> Vec<String> {
+    read_file().lines().map(|line| line.unwrap()).collect()
+}
+
+
+/// Items shared by several rucksacks (or compartments) sorted by priority.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SharedItems {
+    items: Vec<char>,
+}
+
+
+impl SharedItems {
+    fn from_sets(sets: Vec<BTreeSet<char>>) -> SharedItems {
+        let mut sets = sets.into_iter();
+        let first = sets.next().unwrap_or_default();
+        let common = sets.fold(first, |acc, set| acc.intersection(&set).copied().collect());
+        let mut items: Vec<char> = common.into_iter().collect();
+        items.sort_by_key(|&c| get_letter_value(c));
+        SharedItems { items }
+    }
+
+    fn priority(&self) -> u32 {
+        self.items.iter().map(|&c| get_letter_value(c)).sum()
+    }
+
+    fn is_unique(&self) -> bool {
+        self.items.len() == 1
     }
-    println!("Part 1: {}", result);
 }
 
 
-fn get_repeated(chars_index: HashMap<char, [usize; 2]>, size: usize) -> char {
-    let h_size = size / 2;
-    for (k, v) in chars_index.iter() {
-        if v[0] < h_size && v[1] >= h_size {
-            return *k;
-        }
+/// Items that appear in both compartments of a rucksack, `None` if it can
+/// not be split into two compartments of the same length.
+fn get_repeated(line: &str) -> Option<SharedItems> {
+    if !line.len().is_multiple_of(2) || !line.is_char_boundary(line.len() / 2) {
+        return None;
     }
-    panic!("BAD");
+    let (first, second) = line.split_at(line.len() / 2);
+    Some(SharedItems::from_sets(vec![first.chars().collect(), second.chars().collect()]))
+}
+
+
+/// Items that appear in all the rucksacks of a group.
+fn get_repeated_lines(lines: &[String]) -> SharedItems {
+    SharedItems::from_sets(lines.iter().map(|line| line.chars().collect()).collect())
+}
+
+
+/// Rucksack or group that does not share exactly one item, or trailing
+/// group with less than three `rucksacks`. `line` is the 1-based line number
+/// of the rucksack (or of the first one of the group). `shared` is `None`
+/// for rucksacks that can not be split into two compartments.
+#[derive(Debug, PartialEq)]
+struct Violation {
+    line: usize,
+    rucksacks: usize,
+    shared: Option<SharedItems>,
+}
+
+
+fn validate_rucksacks(lines: &[String]) -> Vec<Violation> {
+    lines.iter()
+        .enumerate()
+        .map(|(i, line)| Violation { line: i + 1, rucksacks: 1, shared: get_repeated(line) })
+        .filter(|violation| !violation.shared.as_ref().is_some_and(SharedItems::is_unique))
+        .collect()
+}
+
+
+fn validate_groups(lines: &[String]) -> Vec<Violation> {
+    lines.chunks(3)
+        .enumerate()
+        .map(|(i, group)| Violation { line: 3 * i + 1, rucksacks: group.len(), shared: Some(get_repeated_lines(group)) })
+        .filter(|violation| violation.rucksacks < 3 || !violation.shared.as_ref().is_some_and(SharedItems::is_unique))
+        .collect()
+}
+
+
+fn part1() {
+    let result: u32 = read_lines().iter()
+        .enumerate()
+        .map(|(i, line)| match get_repeated(line) {
+            Some(shared) => shared.priority(),
+            None => panic!("Rucksack at line {} can not be split in two compartments", i + 1),
+        })
+        .sum();
+    println!("Part 1: {}", result);
 }
 
+
 fn part2() {
-    let reader = read_file();
-    let mut result = 0;
-    let mut tracker: HashMap<char, [bool; 3]> = HashMap::new();
-    let mut aux_index = 0;
-    for line in reader.lines() {
-        let line = line.unwrap();
-        for c in line.chars() {
-            let indexes = tracker.entry(c).or_insert([false, false, false]);
-            indexes[aux_index] = true;
-        }
-        aux_index += 1;
-        if aux_index == 3 {
-            result += get_letter_value(get_repeated_lines(&tracker));
-            aux_index = 0;
-            tracker.clear();
-        }
-    }
+    let result: u32 = read_lines().chunks(3).map(|group| get_repeated_lines(group).priority()).sum();
     println!("Part 2: {}", result);
 }
 
 
-fn get_repeated_lines(char_appears: &HashMap<char, [bool; 3]>) -> char {
-    for (k, v) in char_appears.iter() {
-        if v.iter().all(|&x| x) {
-            return *k;
+fn validate() {
+    let lines = read_lines();
+    for violation in validate_rucksacks(&lines) {
+        match violation.shared {
+            Some(shared) => println!("Rucksack at line {} shares {:?}", violation.line, shared.items),
+            None => println!("Rucksack at line {} can not be split in two compartments", violation.line),
+        }
+    }
+    for violation in validate_groups(&lines) {
+        let items = violation.shared.map_or(Vec::new(), |shared| shared.items);
+        if violation.rucksacks < 3 {
+            println!("Group starting at line {} has only {} rucksacks", violation.line, violation.rucksacks);
+        } else {
+            println!("Group starting at line {} shares {:?}", violation.line, items);
         }
     }
-    panic!("BAD");
 }
 
 
 fn main() {
+    if std::env::args().any(|arg| arg == "--validate") {
+        validate();
+        return;
+    }
     part1();
     part2();
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::{get_repeated, validate_groups, validate_rucksacks, SharedItems, Violation};
+
+    fn lines(text: &[&str]) -> Vec<String> {
+        text.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn shared_items() {
+        let shared = SharedItems::from_sets(vec!["aZbc".chars().collect(), "cbZ".chars().collect(), "Zxbc".chars().collect()]);
+        assert_eq!(shared.items, vec!['b', 'c', 'Z']);
+        assert_eq!(shared.priority(), 2 + 3 + 52);
+        assert_eq!(SharedItems::from_sets(Vec::new()).items, Vec::<char>::new());
+        assert_eq!(get_repeated("vJrwpWtwJgWrhcsFMMfFFhFp").unwrap().items, vec!['p']);
+        assert_eq!(get_repeated("abBaBx").unwrap().items, vec!['a', 'B']);
+        assert!(get_repeated("abcd").unwrap().items.is_empty());
+        assert_eq!(get_repeated("abc"), None);
+        assert_eq!(get_repeated("aéb"), None);
+    }
+
+    #[test]
+    fn validation() {
+        let rucksacks = lines(&["vJrwpWtwJgWrhcsFMMfFFhFp", "abBaBx", "abcd", "jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL", "abcab", "aéb"]);
+        let violations = validate_rucksacks(&rucksacks);
+        assert_eq!(violations.iter().map(|v| v.line).collect::<Vec<_>>(), vec![2, 3, 5, 6]);
+        assert_eq!(violations[0].shared.as_ref().unwrap().items, vec!['a', 'B']);
+        assert!(violations[1].shared.as_ref().unwrap().items.is_empty());
+        assert_eq!(violations[2].shared, None);
+        assert_eq!(violations[3].shared, None);
+
+        let rucksacks = lines(&["abc", "bcd", "cde", "xy", "xz", "xw", "ab", "ba"]);
+        assert_eq!(validate_groups(&rucksacks), vec![
+            Violation { line: 7, rucksacks: 2, shared: Some(SharedItems { items: vec!['a', 'b'] }) },
+        ]);
+        let rucksacks = lines(&["abc", "bcd", "bce"]);
+        assert_eq!(validate_groups(&rucksacks)[0].line, 1);
+        assert_eq!(validate_groups(&rucksacks)[0].shared.as_ref().unwrap().items, vec!['b', 'c']);
+    }
+}
@@ -1,5 +1,6 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::collections::BTreeSet;
 
 
 #[derive(Debug)]
@@ -10,10 +11,18 @@ struct Range {
 
 impl Range {
     fn new(text: String) -> Self {
-        let mut t_sp = text.split("-");
-        let start: i32 = t_sp.next().unwrap().parse().unwrap();
-        let end: i32 = t_sp.next().unwrap().parse().unwrap();
-        Self{start, end}
+        Self::parse(&text).unwrap()
+    }
+
+    /// Parses `START-END`, with `START <= END`.
+    fn parse(text: &str) -> Result<Self, String> {
+        let (start, end) = text.split_once('-').ok_or(format!("Bad range: {}", text))?;
+        let start: i32 = start.parse().map_err(|_| format!("Bad range: {}", text))?;
+        let end: i32 = end.parse().map_err(|_| format!("Bad range: {}", text))?;
+        if start > end {
+            return Err(format!("Reversed range: {}", text));
+        }
+        Ok(Self{start, end})
     }
 
     fn copy(&self) -> Self {
@@ -82,6 +91,198 @@ impl OverlapList {
     fn num_elements(&self) -> usize {
         self.list.iter().map(|range| (range.end - range.start + 1) as usize).sum()
     }
+
+    fn contains_number(&self, number: i32) -> bool {
+        self.list.iter().any(|range| range.contains_number(number))
+    }
+
+    fn sections(&self) -> Vec<i32> {
+        self.list.iter().flat_map(|range| range.start..=range.end).collect()
+    }
+}
+
+
+/// Ranges assigned to the elves of a team (one line of the input).
+#[derive(Debug)]
+struct Team {
+    ranges: Vec<Range>,
+}
+
+impl Team {
+    fn new(line: &str) -> Self {
+        Self{ranges: line.split(',').map(|text| Range::new(text.to_string())).collect()}
+    }
+
+    /// Pairs `(i, j)` with `i < j` where one range fully contains the other.
+    fn containment_pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for (i, range1) in self.ranges.iter().enumerate() {
+            for (j, range2) in self.ranges.iter().enumerate().skip(i + 1) {
+                if range1.contains(range2) || range2.contains(range1) {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Pairs `(i, j)` with `i < j` whose ranges share at least one section.
+    fn overlap_pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for (i, range1) in self.ranges.iter().enumerate() {
+            for (j, range2) in self.ranges.iter().enumerate().skip(i + 1) {
+                if range1.overlap(range2).is_some() {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Indexes sorted by start and, for equal starts, by decreasing end so a
+    /// range always comes after the ranges that contain it.
+    fn sweep_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.ranges.len()).collect();
+        order.sort_by_key(|&i| (self.ranges[i].start, std::cmp::Reverse(self.ranges[i].end)));
+        order
+    }
+
+    /// Same as `containment_pairs` in O(n log n + pairs). The active set is
+    /// keyed by end, so the containers of a range are the active ranges
+    /// ending at or after it.
+    fn containment_pairs_sweep(&self) -> Vec<(usize, usize)> {
+        let mut active: BTreeSet<(i32, usize)> = BTreeSet::new();
+        let mut pairs = Vec::new();
+        for index in self.sweep_order() {
+            let range = &self.ranges[index];
+            for &(_, other) in active.range((range.end, 0)..) {
+                pairs.push((other.min(index), other.max(index)));
+            }
+            active.insert((range.end, index));
+        }
+        pairs.sort();
+        pairs
+    }
+
+    /// Same as `overlap_pairs` in O(n log n + pairs). Ranges that end before
+    /// the current start leave the active set, the rest overlap with it.
+    fn overlap_pairs_sweep(&self) -> Vec<(usize, usize)> {
+        let mut active: BTreeSet<(i32, usize)> = BTreeSet::new();
+        let mut pairs = Vec::new();
+        for index in self.sweep_order() {
+            let range = &self.ranges[index];
+            active = active.split_off(&(range.start, 0));
+            for &(_, other) in active.iter() {
+                pairs.push((other.min(index), other.max(index)));
+            }
+            active.insert((range.end, index));
+        }
+        pairs.sort();
+        pairs
+    }
+
+    /// Largest number of ranges that share a single section.
+    fn max_depth(&self) -> usize {
+        let mut events: Vec<(i32, i32)> = self.ranges.iter()
+            .flat_map(|range| [(range.start, 1), (range.end + 1, -1)])
+            .collect();
+        events.sort();
+        let mut depth = 0;
+        let mut max_depth = 0;
+        for (_, delta) in events {
+            depth += delta;
+            max_depth = max_depth.max(depth);
+        }
+        max_depth as usize
+    }
+}
+
+
+/// Section assignments of every team of elves together with the sections
+/// that are assigned to more than one elf of some team (double-booked).
+#[derive(Debug)]
+struct Coverage {
+    teams: Vec<Team>,
+    assigned: OverlapList,
+    double_booked: OverlapList,
+}
+
+impl Coverage {
+    fn new() -> Self {
+        Self{teams: Vec::new(), assigned: OverlapList::new(), double_booked: OverlapList::new()}
+    }
+
+    fn from_reader(reader: BufReader<File>) -> Self {
+        let mut coverage = Self::new();
+        for line in reader.lines() {
+            coverage.add_team(Team::new(&line.unwrap()));
+        }
+        coverage
+    }
+
+    fn add_team(&mut self, team: Team) {
+        for (i, j) in team.overlap_pairs_sweep() {
+            self.double_booked.add(team.ranges[i].overlap(&team.ranges[j]).unwrap());
+        }
+        for range in team.ranges.iter() {
+            self.assigned.add(range.copy());
+        }
+        self.teams.push(team);
+    }
+
+    fn double_booked(&self) -> Vec<i32> {
+        self.double_booked.sections()
+    }
+
+    fn num_elements(&self) -> usize {
+        self.double_booked.num_elements()
+    }
+
+    /// Indexes of the teams where at least one of the elves covers `section`.
+    fn teams_covering(&self, section: i32) -> Vec<usize> {
+        self.teams.iter()
+            .enumerate()
+            .filter(|(_, team)| team.ranges.iter().any(|range| range.contains_number(section)))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Ranges covering `section` with the index of their team.
+    fn ranges_covering(&self, section: i32) -> Vec<(usize, &Range)> {
+        self.teams.iter()
+            .enumerate()
+            .flat_map(|(index, team)| team.ranges.iter().map(move |range| (index, range)))
+            .filter(|(_, range)| range.contains_number(section))
+            .collect()
+    }
+
+    fn uncovered(&self, range: &Range) -> Vec<i32> {
+        (range.start..=range.end).filter(|&section| !self.assigned.contains_number(section)).collect()
+    }
+
+    /// One char per section: `.` nobody covers it, `-` covered and `X`
+    /// double-booked. Rows hold 50 sections and start with the first ID.
+    fn map(&self) -> String {
+        let (Some(first), Some(last)) = (self.assigned.list.first(), self.assigned.list.last()) else {
+            return String::new();
+        };
+        let sections: Vec<i32> = (first.start..=last.end).collect();
+        let mut text = String::new();
+        for row in sections.chunks(50) {
+            text.push_str(&format!("{:>4} ", row[0]));
+            for &section in row {
+                if self.double_booked.contains_number(section) {
+                    text.push('X');
+                } else if self.assigned.contains_number(section) {
+                    text.push('-');
+                } else {
+                    text.push('.');
+                }
+            }
+            text.push('\n');
+        }
+        text
+    }
 }
 
 fn read_file() -> BufReader<File> {
@@ -94,39 +295,141 @@ fn part1() {
     let reader = read_file();
     let mut result: usize = 0;
     for line in reader.lines() {
-        let line = line.unwrap();
-        let mut line_sp = line.split(",");
-        let range1 = Range::new(line_sp.next().unwrap().to_string());
-        let range2 = Range::new(line_sp.next().unwrap().to_string());
-        result += (range1.contains(&range2) || range2.contains(&range1)) as usize;
+        let team = Team::new(&line.unwrap());
+        result += !team.containment_pairs().is_empty() as usize;
     }
     println!("Part 1: {}", result);
 }
 
 
 fn part2() {
-    let reader = read_file();
-    let mut overlaps = OverlapList::new();
-    let mut result = 0;
-    for line in reader.lines() {
-        let line = line.unwrap();
-        let mut line_sp = line.split(",");
-        let range1 = Range::new(line_sp.next().unwrap().to_string());
-        let range2 = Range::new(line_sp.next().unwrap().to_string());
-        let overlap = range1.overlap(&range2);
-        match overlap {
-            Some(range) => {
-                overlaps.add(range);
-                result += 1;
-            },
-            None => {},
+    let coverage = Coverage::from_reader(read_file());
+    let result = coverage.teams.iter()
+        .filter(|team| !team.overlap_pairs().is_empty())
+        .count();
+    println!("Part 2: {}", result);
+    println!("Double-booked sections: {}", coverage.num_elements());
+}
+
+
+fn print_teams() {
+    for (index, line) in read_file().lines().enumerate() {
+        let team = Team::new(&line.unwrap());
+        println!(
+            "Line {}: contained {:?}, overlapping {:?}, max depth {}",
+            index + 1, team.containment_pairs_sweep(), team.overlap_pairs_sweep(), team.max_depth()
+        );
+    }
+}
+
+
+/// Value of the option `name`, an error if it is given without a value.
+fn option<'a>(args: &'a [String], name: &str) -> Result<Option<&'a String>, String> {
+    match args.iter().position(|arg| arg == name) {
+        Some(index) => args.get(index + 1).map(Some).ok_or(format!("Missing value for {}", name)),
+        None => Ok(None),
+    }
+}
+
+
+/// Uncovered sections are searched in the `--range START-END` option, or
+/// between the first and last assigned sections. `--section N` lists the
+/// ranges covering that section.
+fn print_coverage(args: &[String]) -> Result<(), String> {
+    let range = option(args, "--range")?.map(|text| Range::parse(text)).transpose()?;
+    let section: Option<i32> = option(args, "--section")?
+        .map(|text| text.parse().map_err(|_| format!("Bad section: {}", text)))
+        .transpose()?;
+    let coverage = Coverage::from_reader(read_file());
+    print!("{}", coverage.map());
+    println!("Double-booked: {:?}", coverage.double_booked());
+    let range = range.or_else(|| match (coverage.assigned.list.first(), coverage.assigned.list.last()) {
+        (Some(first), Some(last)) => Some(Range{start: first.start, end: last.end}),
+        _ => None,
+    });
+    if let Some(range) = range {
+        println!("Uncovered in {}-{}: {:?}", range.start, range.end, coverage.uncovered(&range));
+    }
+    match section {
+        Some(section) => {
+            println!("Teams covering {}: {}", section, coverage.teams_covering(section).len());
+            for (index, range) in coverage.ranges_covering(section) {
+                println!("Line {}: {}-{}", index + 1, range.start, range.end);
+            }
         }
+        None => if let Some(section) = coverage.double_booked().first() {
+            println!("Teams covering {}: {}", section, coverage.teams_covering(*section).len());
+        },
     }
-    println!("Part 2: {}", result);
+    Ok(())
 }
 
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--coverage") {
+        if let Err(error) = print_coverage(&args) {
+            println!("{}", error);
+        }
+        return;
+    }
+    if args.iter().any(|arg| arg == "--teams") {
+        print_teams();
+        return;
+    }
     part1();
     part2();
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Coverage, Range, Team};
+
+    #[test]
+    fn sweep_matches_pairwise() {
+        let lines = ["2-4,6-8", "2-8,3-7", "6-6,4-6", "2-6,4-8", "1-9,2-3,2-3,5-7,8-12,20-25", "5-5,5-5,1-4"];
+        for line in lines {
+            let team = Team::new(line);
+            assert_eq!(team.containment_pairs_sweep(), team.containment_pairs());
+            assert_eq!(team.overlap_pairs_sweep(), team.overlap_pairs());
+        }
+    }
+
+    #[test]
+    fn max_depth() {
+        assert_eq!(Team::new("2-4,6-8").max_depth(), 1);
+        assert_eq!(Team::new("2-4,4-8").max_depth(), 2);
+        assert_eq!(Team::new("1-9,2-3,2-3,5-7,8-12").max_depth(), 3);
+    }
+
+    #[test]
+    fn coverage() {
+        let mut coverage = Coverage::new();
+        assert_eq!(coverage.map(), "");
+        assert!(coverage.double_booked().is_empty());
+        coverage.add_team(Team::new("2-4,3-6"));
+        coverage.add_team(Team::new("10-12,1-2"));
+        coverage.add_team(Team::new("11-11,12-13,11-12"));
+        assert_eq!(coverage.double_booked(), vec![3, 4, 11, 12]);
+        assert_eq!(coverage.num_elements(), 4);
+        assert_eq!(coverage.teams_covering(2), vec![0, 1]);
+        assert_eq!(coverage.teams_covering(12), vec![1, 2]);
+        assert!(coverage.teams_covering(8).is_empty());
+        let ranges: Vec<(usize, i32, i32)> = coverage.ranges_covering(11).iter()
+            .map(|(index, range)| (*index, range.start, range.end))
+            .collect();
+        assert_eq!(ranges, vec![(1, 10, 12), (2, 11, 11), (2, 11, 12)]);
+        assert_eq!(coverage.uncovered(&Range{start: 0, end: 15}), vec![0, 7, 8, 9, 14, 15]);
+        assert_eq!(coverage.map(), "   1 --XX--...-XX-\n");
+    }
+
+    #[test]
+    fn parse_range() {
+        let range = Range::parse("3-9").unwrap();
+        assert_eq!((range.start, range.end), (3, 9));
+        assert_eq!(Range::parse("9-3").unwrap_err(), "Reversed range: 9-3");
+        assert_eq!(Range::parse("3").unwrap_err(), "Bad range: 3");
+        assert_eq!(Range::parse("3-x").unwrap_err(), "Bad range: 3-x");
+    }
+}
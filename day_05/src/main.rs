@@ -1,83 +1,403 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::fmt;
 
 
 fn read_file() -> BufReader<File> {
-    let file = File::open("../input.txt").unwrap();
+    let path = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .unwrap_or_else(|| "../input.txt".to_string());
+    let file = File::open(path).unwrap();
     BufReader::new(file)
 }
 
-fn initial_state() -> [Vec<char>; 9] {
-    [
-        vec!['H', 'L', 'R', 'F', 'B', 'C', 'J', 'M'],
-        vec!['D', 'C', 'Z'],
-        vec!['W', 'G', 'N', 'C', 'F', 'J', 'H'],
-        vec!['B', 'S', 'T', 'M', 'D', 'J', 'P'],
-        vec!['J', 'R', 'D', 'C', 'N'],
-        vec!['Z', 'G', 'J', 'P', 'Q', 'D', 'L', 'W'],
-        vec!['H', 'R', 'F', 'T', 'Z', 'P'],
-        vec!['G', 'M', 'V', 'L'],
-        vec!['J', 'R', 'Q', 'F', 'P', 'G', 'B', 'C']
-    ]
+
+#[derive(Debug, PartialEq)]
+enum DrawingError {
+    Empty,
+    BadNumbering { column: usize },
+    MisalignedCrate { line: usize, column: usize },
+    TooWide { line: usize },
+    FloatingCrate { line: usize, stack: usize },
 }
 
-fn part1() {
-    let reader = read_file();
-    let mut initial = initial_state();
-    for v in initial.iter_mut() {
-        v.reverse();
-    }
-    for line in reader.lines() {
-        let line = line.unwrap();
-        if !line.starts_with("move") {
-            continue;
+impl fmt::Display for DrawingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DrawingError::Empty => write!(f, "the input has no crate drawing"),
+            DrawingError::BadNumbering { column } => {
+                write!(f, "bad stack numbering at column {}", column)
+            }
+            DrawingError::MisalignedCrate { line, column } => {
+                write!(f, "misaligned crate at line {}, column {}", line, column)
+            }
+            DrawingError::TooWide { line } => {
+                write!(f, "line {} is wider than the numbering row", line)
+            }
+            DrawingError::FloatingCrate { line, stack } => {
+                write!(f, "crate of stack {} at line {} has nothing below", stack, line)
+            }
         }
-        let line_sp: Vec<usize> = line
-            .split_whitespace()
-            .filter(|x| x.parse::<usize>().is_ok())
-            .map(|x| x.parse().unwrap()).collect();
-        for _ in 0..line_sp[0] {
-            let value = initial[line_sp[1]-1].pop().unwrap();
-            initial[line_sp[2]-1].push(value);
+    }
+}
+
+
+/// Parses the numbering row (` 1   2   3 `) and returns the number of stacks.
+/// Labels must be consecutive starting at 1 and aligned with the crates.
+fn parse_numbering(line: &str) -> Result<usize, DrawingError> {
+    let chars: Vec<char> = line.trim_end().chars().collect();
+    for (index, cell) in chars.chunks(4).enumerate() {
+        let label: String = cell.iter().collect();
+        if cell[0] != ' ' || label.trim() != (index + 1).to_string() {
+            return Err(DrawingError::BadNumbering { column: 4 * index + 1 });
         }
     }
-    let mut result = String::new();
-    for i in 0..initial.len() {
-        result.push(initial[i].pop().unwrap());
+    if chars.is_empty() {
+        return Err(DrawingError::BadNumbering { column: 1 });
     }
-    println!("Part 1: {}", result);
+    Ok(chars.len().div_ceil(4))
 }
 
 
-fn part2() {
-    let reader = read_file();
-    let mut initial = initial_state();
-    // let mut initial = initial_test();
-    for v in initial.iter_mut() {
-        v.reverse();
-    }
-    for line in reader.lines() {
-        let line = line.unwrap();
-        if !line.starts_with("move") {
-            continue;
+/// Parses the crate drawing that precedes the moves. Stacks are returned
+/// bottom first so the top crate is the last element.
+fn parse_stacks(drawing: &[String]) -> Result<Vec<Vec<char>>, DrawingError> {
+    let (numbering, rows) = drawing.split_last().ok_or(DrawingError::Empty)?;
+    let n_stacks = parse_numbering(numbering)?;
+    let mut stacks: Vec<Vec<char>> = vec![Vec::new(); n_stacks];
+    for (index, row) in rows.iter().enumerate().rev() {
+        let line = index + 1;
+        let chars: Vec<char> = row.trim_end().chars().collect();
+        if chars.len() > 4 * n_stacks - 1 {
+            return Err(DrawingError::TooWide { line });
+        }
+        for (stack, cell) in chars.chunks(4).enumerate() {
+            let column = 4 * stack + 1;
+            match cell {
+                ['[', name, ']', rest @ ..] if name.is_ascii_alphabetic() && rest.iter().all(|&c| c == ' ') => {
+                    if stacks[stack].len() + index + 1 != rows.len() {
+                        return Err(DrawingError::FloatingCrate { line, stack: stack + 1 });
+                    }
+                    stacks[stack].push(*name);
+                }
+                cell if cell.iter().all(|&c| c == ' ') => {}
+                _ => return Err(DrawingError::MisalignedCrate { line, column }),
+            }
+        }
+    }
+    Ok(stacks)
+}
+
+
+/// Splits the input in the crate stacks and the `move` lines, also returning
+/// the number of lines before the moves.
+fn read_input() -> (Vec<Vec<char>>, Vec<String>, usize) {
+    let mut lines = read_file().lines().map(|line| line.unwrap());
+    let drawing: Vec<String> = lines.by_ref().take_while(|line| !line.is_empty()).collect();
+    let stacks = match parse_stacks(&drawing) {
+        Ok(stacks) => stacks,
+        Err(error) => panic!("Invalid crate drawing: {}", error),
+    };
+    (stacks, lines.collect(), drawing.len() + 1)
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Move {
+    count: usize,
+    from: usize,
+    to: usize,
+}
+
+impl Move {
+    /// Parses `move N from A to B`.
+    fn parse(line: &str) -> Result<Self, MoveError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let malformed = || MoveError::Malformed { line: line.to_string() };
+        match words.as_slice() {
+            ["move", count, "from", from, "to", to] => Ok(Self{
+                count: count.parse().map_err(|_| malformed())?,
+                from: from.parse().map_err(|_| malformed())?,
+                to: to.parse().map_err(|_| malformed())?,
+            }),
+            _ => Err(malformed()),
         }
-        let line_sp: Vec<usize> = line
-            .split_whitespace()
-            .filter(|x| x.parse::<usize>().is_ok())
-            .map(|x| x.parse().unwrap()).collect();
-        let len = initial[line_sp[1] - 1].len();
-        let mut piece: Vec<char> = initial[line_sp[1]-1].drain(len-line_sp[0]..).collect();
-        initial[line_sp[2]-1].append(&mut piece);
     }
-    let mut result = String::new();
-    for i in 0..initial.len() {
-        result.push(initial[i].pop().unwrap());
+}
+
+
+#[derive(Debug, PartialEq)]
+enum MoveError {
+    Malformed { line: String },
+    StackOutOfRange { stack: usize },
+    NotEnoughCrates { stack: usize, needed: usize, available: usize },
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::Malformed { line } => write!(f, "malformed move `{}`", line),
+            MoveError::StackOutOfRange { stack } => write!(f, "stack {} does not exist", stack),
+            MoveError::NotEnoughCrates { stack, needed, available } => {
+                write!(f, "stack {} has {} crates but {} are moved", stack, available, needed)
+            }
+        }
+    }
+}
+
+
+/// A crane picks the top `count` crates of a stack and returns them in the
+/// order they are pushed onto the destination.
+trait Crane {
+    fn lift(&self, crates: Vec<char>) -> Vec<char>;
+
+    fn apply(&self, stacks: &mut [Vec<char>], mv: Move) -> Result<(), MoveError> {
+        for stack in [mv.from, mv.to] {
+            if stack == 0 || stack > stacks.len() {
+                return Err(MoveError::StackOutOfRange { stack });
+            }
+        }
+        let source = &mut stacks[mv.from - 1];
+        if source.len() < mv.count {
+            return Err(MoveError::NotEnoughCrates {
+                stack: mv.from, needed: mv.count, available: source.len()
+            });
+        }
+        let len = source.len();
+        let piece: Vec<char> = source.drain(len - mv.count..).collect();
+        stacks[mv.to - 1].extend(self.lift(piece));
+        Ok(())
+    }
+}
+
+
+/// Moves the crates one at a time, so the block ends up reversed.
+struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn lift(&self, mut crates: Vec<char>) -> Vec<char> {
+        crates.reverse();
+        crates
+    }
+}
+
+
+/// Moves the whole block at once keeping the order.
+struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+    fn lift(&self, crates: Vec<char>) -> Vec<char> {
+        crates
+    }
+}
+
+
+/// Replays the moves yielding the state of the stacks after each of them.
+/// It ends after the first invalid move.
+struct Replay<'a, C: Crane + ?Sized> {
+    crane: &'a C,
+    stacks: Vec<Vec<char>>,
+    moves: std::vec::IntoIter<Move>,
+    failed: bool,
+}
+
+impl<'a, C: Crane + ?Sized> Replay<'a, C> {
+    fn new(crane: &'a C, stacks: Vec<Vec<char>>, moves: Vec<Move>) -> Self {
+        Self{crane, stacks, moves: moves.into_iter(), failed: false}
+    }
+}
+
+impl<C: Crane + ?Sized> Iterator for Replay<'_, C> {
+    type Item = Result<(Move, Vec<Vec<char>>), MoveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let mv = self.moves.next()?;
+        let step = self.crane.apply(&mut self.stacks, mv).map(|_| (mv, self.stacks.clone()));
+        self.failed = step.is_err();
+        Some(step)
+    }
+}
+
+
+/// Draws the stacks the same way as the puzzle input.
+fn render(stacks: &[Vec<char>]) -> String {
+    let height = stacks.iter().map(|stack| stack.len()).max().unwrap_or(0);
+    let mut text = String::new();
+    for level in (0..height).rev() {
+        let row: Vec<String> = stacks.iter()
+            .map(|stack| match stack.get(level) {
+                Some(name) => format!("[{}]", name),
+                None => "   ".to_string(),
+            })
+            .collect();
+        text.push_str(row.join(" ").trim_end());
+        text.push('\n');
+    }
+    let numbering: Vec<String> = (1..=stacks.len()).map(|i| format!(" {:<2}", i)).collect();
+    text.push_str(&numbering.join(" "));
+    text.push('\n');
+    text
+}
+
+
+fn read_moves() -> (Vec<Vec<char>>, Vec<Move>) {
+    let (stacks, lines, offset) = read_input();
+    let moves = lines.iter()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| match Move::parse(line) {
+            Ok(mv) => mv,
+            Err(error) => panic!("Invalid move at line {}: {}", offset + index + 1, error),
+        })
+        .collect();
+    (stacks, moves)
+}
+
+
+fn top_crates(stacks: &[Vec<char>]) -> String {
+    stacks.iter().filter_map(|stack| stack.last()).collect()
+}
+
+
+fn solve(crane: &impl Crane) -> String {
+    let (mut stacks, moves) = read_moves();
+    for mv in moves {
+        if let Err(error) = crane.apply(&mut stacks, mv) {
+            panic!("Invalid move {:?}: {}", mv, error);
+        }
+    }
+    top_crates(&stacks)
+}
+
+
+fn part1() {
+    println!("Part 1: {}", solve(&CrateMover9000));
+}
+
+
+fn part2() {
+    println!("Part 2: {}", solve(&CrateMover9001));
+}
+
+
+fn replay() {
+    let crane: Box<dyn Crane> = if std::env::args().any(|arg| arg == "--9001") {
+        Box::new(CrateMover9001)
+    } else {
+        Box::new(CrateMover9000)
+    };
+    let (stacks, moves) = read_moves();
+    println!("{}", render(&stacks));
+    for step in Replay::new(crane.as_ref(), stacks, moves) {
+        match step {
+            Ok((mv, stacks)) => println!("move {} from {} to {}\n{}", mv.count, mv.from, mv.to, render(&stacks)),
+            Err(error) => {
+                println!("Error: {}", error);
+                break;
+            }
+        }
     }
-    println!("Part 2: {}", result);
 }
 
 
 fn main() {
+    if std::env::args().any(|arg| arg == "--replay") {
+        replay();
+        return;
+    }
     part1();
     part2();
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_stacks, render, Crane, CrateMover9000, CrateMover9001, DrawingError, Move, MoveError, Replay};
+
+    fn move_(line: &str) -> Move {
+        Move::parse(line).unwrap()
+    }
+
+    fn drawing(text: &str) -> Vec<String> {
+        text.lines().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_example() {
+        let stacks = parse_stacks(&drawing("    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 "));
+        assert_eq!(stacks, Ok(vec![vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['P']]));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse_stacks(&[]), Err(DrawingError::Empty));
+        assert_eq!(
+            parse_stacks(&drawing("[A] [B]\n 1   3 ")),
+            Err(DrawingError::BadNumbering { column: 5 })
+        );
+        assert_eq!(
+            parse_stacks(&drawing("[A][B]\n 1   2 ")),
+            Err(DrawingError::MisalignedCrate { line: 1, column: 1 })
+        );
+        assert_eq!(
+            parse_stacks(&drawing("[A] [B] [C]\n 1   2 ")),
+            Err(DrawingError::TooWide { line: 1 })
+        );
+        assert_eq!(
+            parse_stacks(&drawing("[A]    \n    [B]\n 1   2 ")),
+            Err(DrawingError::FloatingCrate { line: 1, stack: 1 })
+        );
+    }
+
+    #[test]
+    fn replay_example() {
+        let stacks = vec![vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['P']];
+        let moves = vec![
+            move_("move 1 from 2 to 1"),
+            move_("move 3 from 1 to 3"),
+            move_("move 2 from 2 to 1"),
+            move_("move 1 from 1 to 2"),
+        ];
+        let steps: Vec<_> = Replay::new(&CrateMover9000, stacks.clone(), moves.clone()).collect();
+        assert_eq!(steps.len(), 4);
+        let (_, last) = steps[3].as_ref().unwrap();
+        assert_eq!(render(last), "        [Z]\n        [N]\n        [D]\n[C] [M] [P]\n 1   2   3 \n");
+        let last = Replay::new(&CrateMover9001, stacks, moves).last().unwrap().unwrap().1;
+        assert_eq!(last, vec![vec!['M'], vec!['C'], vec!['P', 'Z', 'N', 'D']]);
+    }
+
+    #[test]
+    fn invalid_moves() {
+        let mut stacks = vec![vec!['A'], vec![]];
+        assert_eq!(
+            CrateMover9000.apply(&mut stacks, move_("move 1 from 2 to 1")),
+            Err(MoveError::NotEnoughCrates { stack: 2, needed: 1, available: 0 })
+        );
+        assert_eq!(
+            CrateMover9001.apply(&mut stacks, move_("move 1 from 1 to 3")),
+            Err(MoveError::StackOutOfRange { stack: 3 })
+        );
+        assert_eq!(stacks, vec![vec!['A'], vec![]]);
+    }
+
+    #[test]
+    fn malformed_moves() {
+        assert_eq!(Move::parse("move 1 from 2 to 3"), Ok(Move { count: 1, from: 2, to: 3 }));
+        for line in ["move", "move 1 from 2", "move x from 2 to 3", "move 1 from 2 to 3 now", "take 1 from 2 to 3"] {
+            assert_eq!(Move::parse(line), Err(MoveError::Malformed { line: line.to_string() }));
+        }
+    }
+
+    #[test]
+    fn replay_stops_at_error() {
+        let stacks = vec![vec!['A', 'B'], vec![]];
+        let moves = vec![move_("move 1 from 1 to 2"), move_("move 1 from 3 to 1"), move_("move 1 from 1 to 2")];
+        let steps: Vec<_> = Replay::new(&CrateMover9000, stacks, moves).collect();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[1], Err(MoveError::StackOutOfRange { stack: 3 }));
+    }
+}
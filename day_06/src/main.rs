@@ -1,43 +1,170 @@
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Read};
+use std::collections::VecDeque;
 
 
-const LEN_BLOCK: usize = 14;
+const PACKET_LEN: usize = 4;
+const MESSAGE_LEN: usize = 14;
 
 
-struct BlockCheck {
-    block: [char; LEN_BLOCK],
-    index: usize
+/// Sliding window over the last `len` bytes that keeps how many times each
+/// byte appears, so checking that they are all different is O(1).
+struct MarkerDetector {
+    len: usize,
+    window: VecDeque<u8>,
+    counts: [usize; 256],
+    repeated: usize,
+    index: usize,
 }
 
-impl BlockCheck {
-    fn new() -> Self {
-        Self {block: ['a'; LEN_BLOCK], index: 0}
+impl MarkerDetector {
+    fn new(len: usize) -> Self {
+        assert!(len > 0, "The marker length must be at least 1");
+        Self {len, window: VecDeque::with_capacity(len + 1), counts: [0; 256], repeated: 0, index: 0}
     }
 
-    fn add_char(&mut self, element: char) -> bool {
-        if self.index < LEN_BLOCK-1 {
-            self.block[self.index] = element;
-            self.index += 1;
-            return false;
+    /// Adds the next byte and returns true if the last `len` bytes are all
+    /// different.
+    fn add_char(&mut self, element: u8) -> bool {
+        self.window.push_back(element);
+        self.counts[element as usize] += 1;
+        if self.counts[element as usize] == 2 {
+            self.repeated += 1;
+        }
+        if self.window.len() > self.len {
+            let old = self.window.pop_front().unwrap();
+            self.counts[old as usize] -= 1;
+            if self.counts[old as usize] == 1 {
+                self.repeated -= 1;
+            }
         }
-        let index_to_replace = self.index % LEN_BLOCK;
-        self.block[index_to_replace] = element;
         self.index += 1;
-        return all_different(&self.block);
+        self.window.len() == self.len && self.repeated == 0
+    }
+
+    /// Empties the window so the next marker does not overlap with the
+    /// previous one. The position in the stream is kept.
+    fn reset(&mut self) {
+        self.window.clear();
+        self.counts = [0; 256];
+        self.repeated = 0;
+    }
+}
+
+
+/// Marker found by the `Decoder`. `position` is the number of characters of
+/// the line processed when the marker completes and `payload` holds the
+/// characters between it and the next marker (or the end of the line).
+#[derive(Debug, PartialEq)]
+struct Marker {
+    line: usize,
+    position: usize,
+    marker: String,
+    payload: String,
+}
+
+
+/// Streaming decoder that yields the markers of each line of a reader.
+/// With `overlap` it reports every position where a window of distinct
+/// characters completes, so markers can share characters and the payload of
+/// a marker followed by an overlapping one is empty. Otherwise the window
+/// starts empty after each marker and markers never overlap.
+struct Decoder<R: Read> {
+    bytes: io::Bytes<BufReader<R>>,
+    len: usize,
+    overlap: bool,
+    line: usize,
+    detector: MarkerDetector,
+    buffer: Vec<u8>,
+    pending: Option<Marker>,
+}
+
+impl<R: Read> Decoder<R> {
+    fn new(reader: R, len: usize, overlap: bool) -> Self {
+        Self {
+            bytes: BufReader::new(reader).bytes(),
+            len,
+            overlap,
+            line: 1,
+            detector: MarkerDetector::new(len),
+            buffer: Vec::new(),
+            pending: None,
+        }
+    }
+
+    /// Closes the pending marker with the characters read since it ended.
+    fn take_pending(&mut self, payload_len: usize) -> Option<Marker> {
+        let mut marker = self.pending.take()?;
+        marker.payload = String::from_utf8_lossy(&self.buffer[..payload_len]).into_owned();
+        Some(marker)
     }
 }
 
+impl<R: Read> Iterator for Decoder<R> {
+    type Item = io::Result<Marker>;
 
-fn all_different(char_array: &[char; LEN_BLOCK]) -> bool {
-    for i in 0..LEN_BLOCK {
-        for j in 0..i {
-            if char_array[i] == char_array[j] {
-                return false
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let element = match self.bytes.next() {
+                None => return self.take_pending(self.buffer.len()).map(Ok),
+                Some(Err(error)) => return Some(Err(error)),
+                Some(Ok(element)) => element,
+            };
+            match element {
+                b'\r' => continue,
+                b'\n' => {
+                    let marker = self.take_pending(self.buffer.len());
+                    self.line += 1;
+                    self.detector = MarkerDetector::new(self.len);
+                    self.buffer.clear();
+                    if marker.is_some() {
+                        return marker.map(Ok);
+                    }
+                }
+                _ => {
+                    self.buffer.push(element);
+                    if !self.detector.add_char(element) {
+                        continue;
+                    }
+                    let start = self.buffer.len().saturating_sub(self.len);
+                    let marker = self.take_pending(start);
+                    let window: Vec<u8> = self.detector.window.iter().copied().collect();
+                    self.pending = Some(Marker {
+                        line: self.line,
+                        position: self.detector.index,
+                        marker: String::from_utf8_lossy(&window).into_owned(),
+                        payload: String::new(),
+                    });
+                    if !self.overlap {
+                        self.detector.reset();
+                    }
+                    self.buffer.clear();
+                    if marker.is_some() {
+                        return marker.map(Ok);
+                    }
+                }
             }
         }
     }
-    true
+}
+
+
+/// Number of characters processed until the packet and the message markers
+/// are complete, computed in a single pass.
+fn find_markers(line: &str) -> (Option<usize>, Option<usize>) {
+    let mut packet = MarkerDetector::new(PACKET_LEN);
+    let mut message = MarkerDetector::new(MESSAGE_LEN);
+    let (mut packet_index, mut message_index) = (None, None);
+    for element in line.bytes() {
+        if packet_index.is_none() && packet.add_char(element) {
+            packet_index = Some(packet.index);
+        }
+        if message.add_char(element) {
+            message_index = Some(message.index);
+            break;
+        }
+    }
+    (packet_index, message_index)
 }
 
 
@@ -47,17 +174,115 @@ fn read_file() -> BufReader<File> {
 }
 
 
-fn part1() {
+fn solve() -> (Option<usize>, Option<usize>) {
     let reader = read_file();
     let line = reader.lines().next().unwrap().unwrap();
-    let mut chars = line.chars();
-    let mut block_check = BlockCheck::new();
-    while !block_check.add_char(chars.next().unwrap()) {
+    find_markers(&line)
+}
+
+
+fn decode(len: usize, overlap: bool) {
+    let mut line = 0;
+    for marker in Decoder::new(File::open("../input.txt").unwrap(), len, overlap) {
+        let marker = marker.unwrap();
+        if marker.line != line {
+            line = marker.line;
+            println!("Line {}:", line);
+        }
+        println!("  {:>6} {} +{} chars", marker.position, marker.marker, marker.payload.len());
     }
-    println!("Part 1: {}", block_check.index);
 }
 
 
 fn main() {
-    part1();
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--decode") {
+        let len = match args.get(index + 1).filter(|arg| !arg.starts_with("--")) {
+            Some(len) => len.parse().unwrap_or(0),
+            None => PACKET_LEN,
+        };
+        if len == 0 {
+            println!("Usage: --decode [N] [--overlap], the marker length N must be at least 1");
+            return;
+        }
+        decode(len, args.iter().any(|arg| arg == "--overlap"));
+        return;
+    }
+    let (packet, message) = solve();
+    println!("Part 1: {}", packet.unwrap());
+    println!("Part 2: {}", message.unwrap());
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{find_markers, Decoder, Marker, MarkerDetector};
+
+    const EXAMPLES: &str = "mjqjpqmgbljsphdztnvjfqwrcgsmlb
+bvwbjplbgvbhsrlpgdmjqwftvncz
+nppdvjthqldpwncqszvftbrmjlhg
+nznrnfrfntjfmvfwmzdfjlvtqnbhcprsfqwfwcrs
+zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw
+";
+
+    fn first_markers(len: usize) -> Vec<(usize, usize)> {
+        let mut firsts: Vec<(usize, usize)> = Vec::new();
+        for marker in Decoder::new(EXAMPLES.as_bytes(), len, false) {
+            let marker = marker.unwrap();
+            if firsts.last().is_none_or(|&(line, _)| line != marker.line) {
+                firsts.push((marker.line, marker.position));
+            }
+        }
+        firsts
+    }
+
+    #[test]
+    fn examples() {
+        assert_eq!(first_markers(4), vec![(1, 7), (2, 5), (3, 6), (4, 10), (5, 11)]);
+        assert_eq!(first_markers(14), vec![(1, 19), (2, 23), (3, 23), (4, 29), (5, 26)]);
+    }
+
+    #[test]
+    fn single_pass() {
+        let expected = [(7, 19), (5, 23), (6, 23), (10, 29), (11, 26)];
+        for (line, (packet, message)) in EXAMPLES.lines().zip(expected) {
+            assert_eq!(find_markers(line), (Some(packet), Some(message)), "{}", line);
+        }
+        assert_eq!(find_markers("abcabd"), (Some(6), None));
+        assert_eq!(find_markers("aaaa"), (None, None));
+    }
+
+    #[test]
+    fn payloads() {
+        let markers: Vec<Marker> = Decoder::new("aabcxxxdefg\n\nabcd".as_bytes(), 3, false)
+            .map(|marker| marker.unwrap())
+            .collect();
+        let expected = vec![
+            Marker {line: 1, position: 4, marker: "abc".to_string(), payload: "xx".to_string()},
+            Marker {line: 1, position: 9, marker: "xde".to_string(), payload: "fg".to_string()},
+            Marker {line: 3, position: 3, marker: "abc".to_string(), payload: "d".to_string()},
+        ];
+        assert_eq!(markers, expected);
+    }
+
+    #[test]
+    fn overlapping() {
+        let markers: Vec<Marker> = Decoder::new("aabcdxx".as_bytes(), 3, true)
+            .map(|marker| marker.unwrap())
+            .collect();
+        let expected = vec![
+            Marker {line: 1, position: 4, marker: "abc".to_string(), payload: "".to_string()},
+            Marker {line: 1, position: 5, marker: "bcd".to_string(), payload: "".to_string()},
+            Marker {line: 1, position: 6, marker: "cdx".to_string(), payload: "x".to_string()},
+        ];
+        assert_eq!(markers, expected);
+        let positions: Vec<usize> = Decoder::new("aabcdxx".as_bytes(), 3, false).map(|marker| marker.unwrap().position).collect();
+        assert_eq!(positions, vec![4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 1")]
+    fn empty_marker() {
+        MarkerDetector::new(0);
+    }
 }
@@ -1,200 +1,412 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::collections::HashMap;
+use std::fmt;
 
+mod query;
 
-trait Component {
-    fn size(&self) -> u32;
-    fn conditional_size(&self) -> u32;
-    fn push_large_enough_dirs(&self, possible_dirs: &mut Vec<u32>, space: u32);
-    fn name(&self) -> &str;
-    fn add(&mut self, component: Box<dyn Component>);
-    fn add_at(&mut self, path: &Vec<String>, depth: usize, component: Box<dyn Component>);
-    fn print(&self, depth: u32) -> String;
+const ROOT: usize = 0;
+
+
+#[derive(Debug)]
+enum NodeKind {
+    File,
+    Dir(HashMap<String, usize>),
 }
 
-struct FileInfo {
+/// Entry of the file system. For directories `size` is the total size of
+/// the subtree, which is updated every time a file is added below it.
+#[derive(Debug)]
+struct Node {
     name: String,
-    size: u32,
+    parent: Option<usize>,
+    size: u64,
+    kind: NodeKind,
 }
 
-impl FileInfo {
-    fn new(name: String, size: u32) -> Self {
-        Self {name, size}
+impl Node {
+    fn is_dir(&self) -> bool {
+        matches!(self.kind, NodeKind::Dir(_))
     }
 }
 
-impl Component for FileInfo {
-    fn size(&self) -> u32 {
-        self.size
+
+/// File system stored as an arena of nodes addressed by index. The root
+/// directory is always the node `ROOT`.
+#[derive(Debug)]
+struct FileSystem {
+    nodes: Vec<Node>,
+}
+
+impl FileSystem {
+    fn new() -> Self {
+        let root = Node {name: String::from("/"), parent: None, size: 0, kind: NodeKind::Dir(HashMap::new())};
+        Self {nodes: vec![root]}
     }
 
-    fn conditional_size(&self) -> u32 {
-        return 0;
+    fn node(&self, id: usize) -> &Node {
+        &self.nodes[id]
     }
 
-    fn push_large_enough_dirs(&self, possible_dirs: &mut Vec<u32>, space: u32) {
-        return;
+    fn size(&self, id: usize) -> u64 {
+        self.nodes[id].size
     }
 
-    fn name(&self) -> &str {
-        &self.name
+    fn parent(&self, id: usize) -> Option<usize> {
+        self.nodes[id].parent
     }
 
-    fn add(&mut self, component: Box<dyn Component>) {
-        panic!("Not possible");
+    fn child(&self, id: usize, name: &str) -> Option<usize> {
+        match &self.nodes[id].kind {
+            NodeKind::Dir(children) => children.get(name).copied(),
+            NodeKind::File => None,
+        }
     }
 
-    fn add_at(&mut self, path: &Vec<String>, depth: usize, component: Box<dyn Component>) {
-        panic!("Not possible");
+    /// Children sorted by name.
+    fn children(&self, id: usize) -> Vec<usize> {
+        let mut children: Vec<usize> = match &self.nodes[id].kind {
+            NodeKind::Dir(children) => children.values().copied().collect(),
+            NodeKind::File => Vec::new(),
+        };
+        children.sort_by(|&a, &b| self.nodes[a].name.cmp(&self.nodes[b].name));
+        children
     }
 
-    fn print(&self, depth: u32) -> String {
-        let mut result = String::new();
-        for _ in 0..depth {
-            result += "  ";
+    /// Adds an entry to the directory `parent`. Adding an entry that is
+    /// already there is a no-op so repeated `ls` outputs do not duplicate
+    /// files.
+    fn add(&mut self, parent: usize, name: &str, file_size: Option<u64>) -> Result<usize, ErrorKind> {
+        if !self.nodes[parent].is_dir() {
+            return Err(ErrorKind::NotADirectory(self.path(parent)));
         }
-        result += "* ";
-        result += &self.name;
-        result += " - ";
-        result += &self.size.to_string();
-        result += "\n";
-        result
+        if let Some(id) = self.child(parent, name) {
+            let old = &self.nodes[id];
+            return match file_size {
+                None if old.is_dir() => Ok(id),
+                Some(size) if !old.is_dir() && old.size == size => Ok(id),
+                _ => Err(ErrorKind::Conflict(name.to_string())),
+            };
+        }
+        let id = self.nodes.len();
+        let kind = if file_size.is_some() { NodeKind::File } else { NodeKind::Dir(HashMap::new()) };
+        let size = file_size.unwrap_or(0);
+        self.nodes.push(Node {name: name.to_string(), parent: Some(parent), size, kind});
+        if let NodeKind::Dir(children) = &mut self.nodes[parent].kind {
+            children.insert(name.to_string(), id);
+        }
+        let mut ancestor = Some(parent);
+        while let Some(dir) = ancestor {
+            self.nodes[dir].size += size;
+            ancestor = self.nodes[dir].parent;
+        }
+        Ok(id)
     }
-}
 
-struct Directory {
-    name: String,
-    components: Vec<Box<dyn Component>>,
-}
+    fn path(&self, id: usize) -> String {
+        let mut names = Vec::new();
+        let mut current = id;
+        while let Some(parent) = self.nodes[current].parent {
+            names.push(self.nodes[current].name.as_str());
+            current = parent;
+        }
+        names.reverse();
+        format!("/{}", names.join("/"))
+    }
 
-impl Directory {
-    fn new(name: String) -> Self {
-        Self {name, components: Vec::new()}
+    /// Node at an absolute path like `/a/e/i`.
+    fn lookup(&self, path: &str) -> Option<usize> {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .try_fold(ROOT, |id, name| self.child(id, name))
     }
 
-    fn get_large_enough_dir_size(&self, needed_space: u32) -> u32 {
-        let mut possible_dirs = Vec::new();
-        self.push_large_enough_dirs(&mut possible_dirs, needed_space);
-        return *possible_dirs.iter().min().unwrap()
+    fn files(&self) -> impl Iterator<Item = (String, u64)> + '_ {
+        (0..self.nodes.len())
+            .filter(|&id| !self.nodes[id].is_dir())
+            .map(|id| (self.path(id), self.nodes[id].size))
     }
-}
 
-impl Component for Directory {
-    fn size(&self) -> u32 {
-        self.components.iter().map(|comp| comp.size()).sum()
+    fn dirs(&self) -> impl Iterator<Item = (String, u64)> + '_ {
+        (0..self.nodes.len())
+            .filter(|&id| self.nodes[id].is_dir())
+            .map(|id| (self.path(id), self.nodes[id].size))
     }
 
-    fn conditional_size(&self) -> u32 {
-        let mut size = self.size();
-        if size > 100000 {
-            size = 0;
-        }
-        for element in self.components.iter() {
-            size += element.conditional_size();
-        }
-        return size;
+    fn conditional_size(&self) -> u64 {
+        self.dirs().map(|(_, size)| size).filter(|&size| size <= 100000).sum()
     }
 
-    fn push_large_enough_dirs(&self, possible_dirs: &mut Vec<u32>, space: u32) {
-        let size = self.size();
-        if size >= space {
-            possible_dirs.push(size);
+    fn print(&self, id: usize, depth: usize) -> String {
+        let node = &self.nodes[id];
+        let mut result = "  ".repeat(depth);
+        if node.is_dir() {
+            result += &format!("- {} ({}):\n", node.name, node.size);
+            for child in self.children(id) {
+                result += &self.print(child, depth + 1);
+            }
+        } else {
+            result += &format!("* {} - {}\n", node.name, node.size);
         }
-        for element in self.components.iter() {
-            element.push_large_enough_dirs(possible_dirs, space);
+        result
+    }
+}
+
+
+fn read_file() -> BufReader<File> {
+    let file = File::open("../input.txt").unwrap();
+    BufReader::new(file)
+}
+
+
+#[derive(Debug, PartialEq)]
+enum ErrorKind {
+    UnknownCommand(String),
+    UnknownDirectory(String),
+    NotADirectory(String),
+    Conflict(String),
+    UnexpectedOutput,
+    BadOutput(String),
+    WrongPwd(String),
+}
+
+/// Error found while interpreting the transcript, `line` is 1-based.
+#[derive(Debug, PartialEq)]
+struct ShellError {
+    line: usize,
+    kind: ErrorKind,
+}
+
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ErrorKind::UnknownCommand(command) => write!(f, "unknown command `{}`", command),
+            ErrorKind::UnknownDirectory(name) => write!(f, "no such directory `{}`", name),
+            ErrorKind::NotADirectory(name) => write!(f, "`{}` is not a directory", name),
+            ErrorKind::Conflict(name) => write!(f, "`{}` listed twice with different content", name),
+            ErrorKind::UnexpectedOutput => write!(f, "output without a command"),
+            ErrorKind::BadOutput(output) => write!(f, "cannot parse output `{}`", output),
+            ErrorKind::WrongPwd(path) => write!(f, "pwd printed `{}` but the current directory differs", path),
         }
     }
+}
+
 
-    fn name(&self) -> &str {
-        &self.name
+#[derive(Debug, PartialEq)]
+enum Command {
+    Cd(String),
+    Ls,
+    Pwd,
+}
+
+impl Command {
+    fn parse(text: &str) -> Result<Self, ErrorKind> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        match words.as_slice() {
+            ["cd", target] => Ok(Command::Cd(target.to_string())),
+            ["ls"] => Ok(Command::Ls),
+            ["pwd"] => Ok(Command::Pwd),
+            _ => Err(ErrorKind::UnknownCommand(text.to_string())),
+        }
     }
+}
+
 
-    fn add(&mut self, component: Box<dyn Component>) {
-        self.components.push(component);
+/// Replays a shell transcript (`$ cd`, `$ ls`, `$ pwd` and their outputs)
+/// building the file system it explores.
+struct Interpreter {
+    file_system: FileSystem,
+    current_dir: usize,
+    last_command: Option<Command>,
+}
+
+impl Interpreter {
+    fn new() -> Self {
+        Self {file_system: FileSystem::new(), current_dir: ROOT, last_command: None}
     }
 
-    fn add_at(&mut self, path: &Vec<String>, depth: usize, component: Box<dyn Component>) {
-        if path.len() == depth {
-            self.add(component);
-        } else {
-            for comp in self.components.iter_mut() {
-                if comp.name() == path[depth] {
-                    comp.add_at(path, depth + 1, component);
-                    return;
-                }
-            }
-            println!("{:?}, {}, {}", path, depth, component.name());
-            panic!();
+    fn cd(&mut self, target: &str) -> Result<(), ErrorKind> {
+        let mut dir = if target.starts_with('/') { ROOT } else { self.current_dir };
+        for name in target.split('/').filter(|name| !name.is_empty()) {
+            dir = match name {
+                "." => dir,
+                ".." => self.file_system.parent(dir).unwrap_or(ROOT),
+                _ => match self.file_system.child(dir, name) {
+                    Some(child) if self.file_system.node(child).is_dir() => child,
+                    _ => {
+                        let path = self.file_system.path(dir);
+                        return Err(ErrorKind::UnknownDirectory(format!("{}/{}", path.trim_end_matches('/'), name)));
+                    }
+                },
+            };
         }
+        self.current_dir = dir;
+        Ok(())
     }
 
-    fn print(&self, depth: u32) -> String {
-        let mut result = String::new();
-        for _ in 0..depth {
-            result += "  ";
-        }
-        result += "- ";
-        result += &self.name;
-        result += " (";
-        result += &self.size().to_string();
-        result += "):\n";
-        for component in self.components.iter() {
-            result += &component.print(depth + 1);
+    fn output(&mut self, text: &str) -> Result<(), ErrorKind> {
+        match self.last_command {
+            Some(Command::Ls) => {}
+            Some(Command::Pwd) if text == self.file_system.path(self.current_dir) => return Ok(()),
+            Some(Command::Pwd) => return Err(ErrorKind::WrongPwd(text.to_string())),
+            _ => return Err(ErrorKind::UnexpectedOutput),
         }
-        result
+        let (name, size) = match text.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["dir", name] => (name.to_string(), None),
+            [size, name] => match size.parse() {
+                Ok(size) => (name.to_string(), Some(size)),
+                Err(_) => return Err(ErrorKind::BadOutput(text.to_string())),
+            },
+            _ => return Err(ErrorKind::BadOutput(text.to_string())),
+        };
+        self.file_system.add(self.current_dir, &name, size).map(|_| ())
     }
-}
 
+    fn execute(&mut self, line: &str) -> Result<(), ErrorKind> {
+        let Some(text) = line.strip_prefix("$ ") else {
+            return self.output(line);
+        };
+        let command = Command::parse(text)?;
+        if let Command::Cd(target) = &command {
+            self.cd(target)?;
+        }
+        self.last_command = Some(command);
+        Ok(())
+    }
 
-fn read_file() -> BufReader<File> {
-    let file = File::open("../input.txt").unwrap();
-    BufReader::new(file)
+    fn run(mut self, lines: impl Iterator<Item = String>) -> Result<FileSystem, ShellError> {
+        for (index, line) in lines.enumerate() {
+            self.execute(&line).map_err(|kind| ShellError {line: index + 1, kind})?;
+        }
+        Ok(self.file_system)
+    }
 }
 
 
-fn build_file_system() -> Directory {
+fn build_file_system() -> FileSystem {
     let reader = read_file();
-    let mut file_system = Directory::new(String::from("/"));
-    let mut current_path = Vec::new();
-    for line in reader.lines() {
-        let line = line.unwrap();
-        if line == "$ cd /" {
-            current_path.clear();
-        } else if line == "$ ls" {
-            continue;
-        } else if line == "$ cd .." {
-            current_path.pop();
-        } else if line.starts_with("$ cd") {
-            let new_dir = line.split_whitespace();
-            let new_dir = new_dir.last().unwrap();
-            current_path.push(String::from(new_dir));
-        } else if line.starts_with("dir") {
-            let new_component = Directory::new(String::from(line.split_whitespace().last().unwrap()));
-            file_system.add_at(&current_path, 0, Box::new(new_component));
-        } else {
-            let mut line_sp = line.split_whitespace();
-            let size: u32 = line_sp.next().unwrap().parse().unwrap();
-            let name = line_sp.next().unwrap();
-            let new_component = FileInfo::new(name.to_string(), size);
-            file_system.add_at(&current_path, 0, Box::new(new_component));
-        }
+    match Interpreter::new().run(reader.lines().map(|line| line.unwrap())) {
+        Ok(file_system) => file_system,
+        Err(error) => panic!("Invalid transcript: {}", error),
     }
-    file_system
 }
 
 
-fn solutions() {
-    let file_system = build_file_system();
-
+fn solutions(file_system: &FileSystem, args: &[String]) {
     let result = file_system.conditional_size();
     println!("Part 1: {}", result);
 
-    let space_to_free = 30000000 - (70000000 - file_system.size());
-    let result = file_system.get_large_enough_dir_size(space_to_free);
-    println!("Part 2: {}", result);
+    let disk_size = query::option(args, "--disk-size").unwrap_or(query::DISK_SIZE);
+    let needed_space = query::option(args, "--target").unwrap_or(query::NEEDED_SPACE);
+    match file_system.dir_to_free(disk_size, needed_space) {
+        Some((_, result)) => println!("Part 2: {}", result),
+        None => println!("Part 2: No directory frees enough space"),
+    }
 }
 
 
 fn main() {
-    solutions();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let file_system = build_file_system();
+    if let Some((command, rest)) = args.split_first() {
+        if query::run(&file_system, command, rest) {
+            return;
+        }
+    }
+    solutions(&file_system, &args);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorKind, FileSystem, Interpreter, ShellError, ROOT};
+
+    fn run(transcript: &str) -> Result<u64, ShellError> {
+        let lines = transcript.lines().map(|line| line.to_string());
+        Interpreter::new().run(lines).map(|file_system| file_system.size(ROOT))
+    }
+
+    #[test]
+    fn repeated_ls_and_absolute_cd() {
+        let transcript = "$ cd /\n$ ls\ndir a\n10 b\n$ cd a\n$ ls\n5 c\n$ cd /\n$ ls\ndir a\n10 b\n$ cd /a\n$ pwd\n/a\n$ ls\n5 c";
+        assert_eq!(run(transcript), Ok(15));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            run("$ cd /\n$ ls\ndir a\n$ cd b"),
+            Err(ShellError {line: 4, kind: ErrorKind::UnknownDirectory("/b".to_string())})
+        );
+        assert_eq!(
+            run("$ cd /\n$ rm a"),
+            Err(ShellError {line: 2, kind: ErrorKind::UnknownCommand("rm a".to_string())})
+        );
+        assert_eq!(
+            run("$ cd /\n$ ls\n10 a\n$ ls\n20 a"),
+            Err(ShellError {line: 5, kind: ErrorKind::Conflict("a".to_string())})
+        );
+        assert_eq!(run("10 a"), Err(ShellError {line: 1, kind: ErrorKind::UnexpectedOutput}));
+    }
+
+    /// `/a/e/i` (584), `/a/f` (29116), `/b` (14848514), `/d/j` (4060174).
+    fn sample() -> FileSystem {
+        let mut file_system = FileSystem::new();
+        let a = file_system.add(ROOT, "a", None).unwrap();
+        let e = file_system.add(a, "e", None).unwrap();
+        file_system.add(e, "i", Some(584)).unwrap();
+        file_system.add(a, "f", Some(29116)).unwrap();
+        file_system.add(ROOT, "b", Some(14848514)).unwrap();
+        let d = file_system.add(ROOT, "d", None).unwrap();
+        file_system.add(d, "j", Some(4060174)).unwrap();
+        file_system
+    }
+
+    #[test]
+    fn parent_links() {
+        let file_system = sample();
+        let i = file_system.lookup("/a/e/i").unwrap();
+        let e = file_system.parent(i).unwrap();
+        let a = file_system.parent(e).unwrap();
+        assert_eq!(file_system.node(e).name, "e");
+        assert_eq!(a, file_system.lookup("/a").unwrap());
+        assert_eq!(file_system.parent(a), Some(ROOT));
+        assert_eq!(file_system.parent(ROOT), None);
+        assert_eq!(file_system.path(i), "/a/e/i");
+    }
+
+    #[test]
+    fn cached_sizes() {
+        let mut file_system = sample();
+        let e = file_system.lookup("/a/e").unwrap();
+        assert_eq!(file_system.size(e), 584);
+        assert_eq!(file_system.size(file_system.lookup("/a").unwrap()), 29700);
+        assert_eq!(file_system.size(ROOT), 18938388);
+        let g = file_system.add(e, "g", None).unwrap();
+        file_system.add(g, "h", Some(16)).unwrap();
+        assert_eq!(file_system.size(g), 16);
+        assert_eq!(file_system.size(e), 600);
+        assert_eq!(file_system.size(file_system.lookup("/a").unwrap()), 29716);
+        assert_eq!(file_system.size(ROOT), 18938404);
+        assert_eq!(file_system.size(file_system.lookup("/d").unwrap()), 4060174);
+    }
+
+    #[test]
+    fn dir_paths() {
+        let mut dirs: Vec<(String, u64)> = sample().dirs().collect();
+        dirs.sort();
+        let expected = [("/", 18938388), ("/a", 29700), ("/a/e", 584), ("/d", 4060174)];
+        assert_eq!(dirs, expected.map(|(path, size)| (path.to_string(), size)));
+    }
+
+    #[test]
+    fn failed_lookups() {
+        let file_system = sample();
+        assert_eq!(file_system.lookup("/"), Some(ROOT));
+        assert_eq!(file_system.lookup("/x"), None);
+        assert_eq!(file_system.lookup("/a/x/i"), None);
+        assert_eq!(file_system.lookup("/b/c"), None);
+        assert_eq!(file_system.lookup("/a/e/i/j"), None);
+    }
 }
@@ -0,0 +1,237 @@
+use std::str::FromStr;
+
+use super::{FileSystem, NodeKind, ROOT};
+
+
+pub const DISK_SIZE: u64 = 70000000;
+pub const NEEDED_SPACE: u64 = 30000000;
+
+
+/// Criteria of the `find` query. Unset fields match everything.
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub name: Option<String>,
+    pub dirs: Option<bool>,
+}
+
+
+impl FileSystem {
+    /// `(depth, path, size)` of the directories up to `max_depth`, listed
+    /// children first as `du` does.
+    pub fn du(&self, max_depth: Option<usize>) -> Vec<(usize, String, u64)> {
+        let mut entries = Vec::new();
+        self.push_du(ROOT, 0, max_depth, &mut entries);
+        entries
+    }
+
+    fn push_du(&self, id: usize, depth: usize, max_depth: Option<usize>, entries: &mut Vec<(usize, String, u64)>) {
+        if max_depth.is_some_and(|max_depth| depth > max_depth) {
+            return;
+        }
+        for child in self.children(id) {
+            if self.node(child).is_dir() {
+                self.push_du(child, depth + 1, max_depth, entries);
+            }
+        }
+        entries.push((depth, self.path(id), self.size(id)));
+    }
+
+    pub fn find(&self, filter: &Filter) -> Vec<(String, u64)> {
+        let mut found: Vec<(String, u64)> = (0..self.nodes.len())
+            .filter(|&id| {
+                let node = self.node(id);
+                filter.min_size.is_none_or(|min| node.size >= min)
+                    && filter.max_size.is_none_or(|max| node.size <= max)
+                    && filter.dirs.is_none_or(|dirs| node.is_dir() == dirs)
+                    && filter.name.as_ref().is_none_or(|pattern| glob_match(pattern, &node.name))
+            })
+            .map(|id| (self.path(id), self.size(id)))
+            .collect();
+        found.sort();
+        found
+    }
+
+    pub fn largest_dirs(&self, count: usize) -> Vec<(String, u64)> {
+        let mut dirs: Vec<(String, u64)> = self.dirs().collect();
+        dirs.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        dirs.truncate(count);
+        dirs
+    }
+
+    /// Smallest directory whose removal leaves `needed_space` free on a disk
+    /// of `disk_size`. `None` if even deleting `/` is not enough.
+    pub fn dir_to_free(&self, disk_size: u64, needed_space: u64) -> Option<(String, u64)> {
+        let free = disk_size.saturating_sub(self.size(ROOT));
+        let space_to_free = needed_space.saturating_sub(free);
+        self.dirs()
+            .filter(|(_, size)| *size >= space_to_free)
+            .min_by_key(|(_, size)| *size)
+    }
+
+    pub fn to_json(&self, id: usize) -> String {
+        let node = self.node(id);
+        let name = json_string(&node.name);
+        match node.kind {
+            NodeKind::File => format!("{{\"name\":{},\"type\":\"file\",\"size\":{}}}", name, node.size),
+            NodeKind::Dir(_) => {
+                let children: Vec<String> = self.children(id).into_iter().map(|child| self.to_json(child)).collect();
+                format!(
+                    "{{\"name\":{},\"type\":\"dir\",\"size\":{},\"children\":[{}]}}",
+                    name, node.size, children.join(",")
+                )
+            }
+        }
+    }
+}
+
+
+fn json_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+
+/// Shell-like pattern matching with `*` (any run of chars) and `?` (one char).
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+
+/// Size with a binary unit suffix as printed by `du -h`.
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["", "K", "M", "G", "T"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        size.to_string()
+    } else if value < 10.0 {
+        format!("{:.1}{}", value, UNITS[unit])
+    } else {
+        format!("{:.0}{}", value, UNITS[unit])
+    }
+}
+
+
+pub fn option<T: FromStr>(args: &[String], name: &str) -> Option<T> {
+    let index = args.iter().position(|arg| arg == name)?;
+    match args.get(index + 1).map(|value| value.parse()) {
+        Some(Ok(value)) => Some(value),
+        _ => panic!("Missing or invalid value for {}", name),
+    }
+}
+
+
+pub fn flag(args: &[String], name: &str) -> bool {
+    args.iter().any(|arg| arg == name)
+}
+
+
+/// Runs a query subcommand (`du`, `find`, `largest`, `free`, `json`, `tree`
+/// or `files`). Returns false if `command` is not one of them.
+pub fn run(file_system: &FileSystem, command: &str, args: &[String]) -> bool {
+    let format_size = |size: u64| if flag(args, "-h") { human_size(size) } else { size.to_string() };
+    match command {
+        "du" => {
+            for (_, path, size) in file_system.du(option(args, "--max-depth")) {
+                println!("{}\t{}", format_size(size), path);
+            }
+        }
+        "find" => {
+            let filter = Filter {
+                min_size: option(args, "--min-size"),
+                max_size: option(args, "--max-size"),
+                name: option(args, "--name"),
+                dirs: option::<String>(args, "--type").map(|kind| kind == "d"),
+            };
+            for (path, size) in file_system.find(&filter) {
+                println!("{}\t{}", format_size(size), path);
+            }
+        }
+        "largest" => {
+            let count = args.first().and_then(|count| count.parse().ok()).unwrap_or(10);
+            for (path, size) in file_system.largest_dirs(count) {
+                println!("{}\t{}", format_size(size), path);
+            }
+        }
+        "free" => {
+            let disk_size = option(args, "--disk-size").unwrap_or(DISK_SIZE);
+            let needed_space = option(args, "--target").unwrap_or(NEEDED_SPACE);
+            match file_system.dir_to_free(disk_size, needed_space) {
+                Some((path, size)) => println!("{}\t{}", format_size(size), path),
+                None => println!("No directory frees enough space"),
+            }
+        }
+        "json" => println!("{}", file_system.to_json(ROOT)),
+        "tree" => {
+            let path = args.first().map_or("/", |path| path.as_str());
+            match file_system.lookup(path) {
+                Some(id) => print!("{}", file_system.print(id, 0)),
+                None => println!("No such file or directory: {}", path),
+            }
+        }
+        "files" => {
+            for (path, size) in file_system.files() {
+                println!("{}\t{}", format_size(size), path);
+            }
+        }
+        _ => return false,
+    }
+    true
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, human_size};
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("*.txt", "b.txt"));
+        assert!(glob_match("?.t*t", "b.txt"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("*.txt", "b.txt.bak"));
+        assert!(!glob_match("?", "ab"));
+    }
+
+    #[test]
+    fn human() {
+        assert_eq!(human_size(512), "512");
+        assert_eq!(human_size(1536), "1.5K");
+        assert_eq!(human_size(48381165), "46M");
+    }
+}
@@ -1,241 +1,287 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::collections::HashSet;
 
 
-struct TreeRow {
-    highest: u32,
-    visible_right: Vec<(usize, u32)>,
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    Left,
+    Right,
+    Top,
+    Bottom,
 }
 
-impl TreeRow {
-    fn new() -> Self {
-        Self {highest: 0, visible_right: Vec::new()}
+impl Direction {
+    const ALL: [Direction; 4] = [Direction::Left, Direction::Right, Direction::Top, Direction::Bottom];
+}
+
+
+/// Height grid with the viewing distance and the visibility of every tree
+/// in each direction (indexed as `Direction::ALL`).
+struct Forest {
+    heights: Vec<Vec<u32>>,
+    distances: Vec<Vec<[u32; 4]>>,
+    visible: Vec<Vec<[bool; 4]>>,
+    n_rows: usize,
+    n_cols: usize,
+}
+
+impl Forest {
+    fn from(heights: Vec<Vec<u32>>) -> Self {
+        let n_rows = heights.len();
+        let n_cols = heights.first().map_or(0, |row| row.len());
+        let mut forest = Self {
+            heights,
+            distances: vec![vec![[0; 4]; n_cols]; n_rows],
+            visible: vec![vec![[false; 4]; n_cols]; n_rows],
+            n_rows,
+            n_cols,
+        };
+        forest.calculate();
+        forest
     }
 
-    fn add_tree(&mut self, index: usize, height: u32) -> bool {
-        if self.visible_right.len() == 0 {
-            self.highest = height;
-            self.visible_right.push((index, height));
-            return true;
+    /// Fills the distances and visibility with one monotonic stack per row
+    /// and column and direction, O(rows × cols) in total.
+    fn calculate(&mut self) {
+        for row_ind in 0..self.n_rows {
+            let cells: Vec<(usize, usize)> = (0..self.n_cols).map(|col_ind| (row_ind, col_ind)).collect();
+            self.look_along(&cells, 0);
+            let cells: Vec<(usize, usize)> = cells.into_iter().rev().collect();
+            self.look_along(&cells, 1);
         }
-        if height >= self.highest {
-            let result = height > self.highest;
-            self.highest = height;
-            self.visible_right = vec![(index, height)];
-            return result;
+        for col_ind in 0..self.n_cols {
+            let cells: Vec<(usize, usize)> = (0..self.n_rows).map(|row_ind| (row_ind, col_ind)).collect();
+            self.look_along(&cells, 2);
+            let cells: Vec<(usize, usize)> = cells.into_iter().rev().collect();
+            self.look_along(&cells, 3);
         }
-        self.add_visible_right(index, height);
-        return false;
     }
 
-    fn add_visible_right(&mut self, index: usize, height: u32) {
-        let mut index_to_add = 0;
-        for (i, (_, tree_height)) in self.visible_right.iter().enumerate() {
-            if tree_height <= &height {
-                index_to_add = i;
-                break;
+    /// Every tree of `cells` looks back towards the first cell. The stack
+    /// keeps the positions of the trees that can still block the view of the
+    /// next ones, i.e. with decreasing heights.
+    fn look_along(&mut self, cells: &[(usize, usize)], dir_ind: usize) {
+        let mut stack: Vec<usize> = Vec::new();
+        for (position, &(row_ind, col_ind)) in cells.iter().enumerate() {
+            let height = self.heights[row_ind][col_ind];
+            while let Some(&top) = stack.last() {
+                let (top_row, top_col) = cells[top];
+                if self.heights[top_row][top_col] >= height {
+                    break;
+                }
+                stack.pop();
             }
+            let (distance, visible) = match stack.last() {
+                Some(&blocker) => (position - blocker, false),
+                None => (position, true),
+            };
+            self.distances[row_ind][col_ind][dir_ind] = distance as u32;
+            self.visible[row_ind][col_ind][dir_ind] = visible;
+            stack.push(position);
         }
-        if index_to_add > 0 {
-            self.visible_right.truncate(index_to_add);
-        }
-        self.visible_right.push((index, height));
     }
 
-    fn get_right_visibles(&self) -> Vec<usize> {
-        self.visible_right.iter().map(|t| t.0).collect()
+    fn visible_from(&self, row_ind: usize, col_ind: usize) -> Vec<Direction> {
+        Direction::ALL.iter()
+            .zip(self.visible[row_ind][col_ind].iter())
+            .filter(|(_, &visible)| visible)
+            .map(|(direction, _)| *direction)
+            .collect()
     }
 
-    fn clear(&mut self) {
-        self.highest = 0;
-        self.visible_right.clear();
+    /// Viewing distances as (left, right, top, bottom).
+    fn viewing_distances(&self, row_ind: usize, col_ind: usize) -> [u32; 4] {
+        self.distances[row_ind][col_ind]
     }
-}
 
-struct Forest {
-    current_row_index: usize,
-    current_row: TreeRow,
-    columns: Vec<TreeRow>,
-    visible_trees: HashSet<(usize, usize)>,
-}
+    fn scenic_score(&self, row_ind: usize, col_ind: usize) -> u64 {
+        self.distances[row_ind][col_ind].iter().map(|&distance| distance as u64).product()
+    }
 
-impl Forest {
-    fn new() -> Self {
-        Self {
-            current_row_index: 0,
-            current_row: TreeRow::new(),
-            columns: Vec::new(),
-            visible_trees: HashSet::new(),
-        }
+    fn visibility_mask(&self) -> Vec<Vec<bool>> {
+        self.visible.iter()
+            .map(|row| row.iter().map(|tree| tree.iter().any(|&visible| visible)).collect())
+            .collect()
     }
 
-    fn add_tree(&mut self, row_index: usize, column_index: usize, height: u32) {
-        if row_index != self.current_row_index {
-            self.collect_last_row();
-            self.current_row_index = row_index;
-            self.current_row.clear();
-        }
-        if self.columns.len() <= column_index {
-            self.columns.push(TreeRow::new());
-        }
-        let mut added = self.current_row.add_tree(column_index, height);
-        added |= self.columns[column_index].add_tree(row_index, height);
-        if added {
-            self.visible_trees.insert((row_index, column_index));
-        }
+    fn score_matrix(&self) -> Vec<Vec<u64>> {
+        (0..self.n_rows)
+            .map(|row_ind| (0..self.n_cols).map(|col_ind| self.scenic_score(row_ind, col_ind)).collect())
+            .collect()
+    }
+
+    fn number_of_visible_trees(&self) -> usize {
+        self.visibility_mask().iter().flatten().filter(|&&visible| visible).count()
+    }
+
+    fn find_max_fov(&self) -> u64 {
+        self.score_matrix().iter().flatten().copied().max().unwrap_or(0)
     }
 
-    fn collect_last_row(&mut self) {
-        for col_ind in self.current_row.get_right_visibles().iter() {
-            self.visible_trees.insert((self.current_row_index, *col_ind));
+    fn explain(&self, row_ind: usize, col_ind: usize) -> String {
+        let mut text = format!(
+            "Tree at row {}, column {} with height {}\n",
+            row_ind, col_ind, self.heights[row_ind][col_ind]
+        );
+        let visible_from = self.visible_from(row_ind, col_ind);
+        if visible_from.is_empty() {
+            text += "  Not visible from outside\n";
+        } else {
+            text += &format!("  Visible from {:?}\n", visible_from);
         }
+        for (direction, distance) in Direction::ALL.iter().zip(self.viewing_distances(row_ind, col_ind)) {
+            text += &format!("  Viewing distance {:?}: {}\n", direction, distance);
+        }
+        text += &format!("  Scenic score: {}\n", self.scenic_score(row_ind, col_ind));
+        text
     }
 
-    fn collect_columns(&mut self) {
-        for (col_ind, tree_row) in self.columns.iter().enumerate() {
-            for row_ind in tree_row.get_right_visibles().iter() {
-                self.visible_trees.insert((*row_ind, col_ind));
-            }
+    /// Scenic scores as a binary grayscale PGM image scaled to the maximum.
+    fn scores_pgm(&self) -> Vec<u8> {
+        let max_score = self.find_max_fov().max(1);
+        let mut image = format!("P5\n{} {}\n255\n", self.n_cols, self.n_rows).into_bytes();
+        for row in self.score_matrix() {
+            image.extend(row.iter().map(|&score| (score * 255 / max_score) as u8));
         }
+        image
     }
 
-    fn number_of_visible_trees(&self) -> usize {
-        self.visible_trees.len()
+    fn scores_csv(&self) -> String {
+        self.score_matrix().iter()
+            .map(|row| row.iter().map(|score| score.to_string()).collect::<Vec<_>>().join(","))
+            .collect::<Vec<_>>()
+            .join("\n") + "\n"
     }
 }
 
+
+/// Random forest of the given size, used to test large inputs.
+fn generate_heights(n_rows: usize, n_cols: usize, seed: u64) -> Vec<Vec<u32>> {
+    let mut state = seed;
+    let mut next = move || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((state >> 33) % 10) as u32
+    };
+    (0..n_rows).map(|_| (0..n_cols).map(|_| next()).collect()).collect()
+}
+
+
 fn read_file() -> BufReader<File> {
     let file = File::open("../input.txt").unwrap();
     BufReader::new(file)
 }
 
 
-fn part1() {
+fn read_forest() -> Forest {
     let reader = read_file();
-    let mut forest = Forest::new();
-    for (row_ind, line) in reader.lines().enumerate() {
-        let line = line.unwrap();
-        for (col_ind, c) in line.chars().enumerate() {
-            let height: u32 = c.to_digit(10).unwrap();
-            forest.add_tree(row_ind, col_ind, height);
+    let mut data: Vec<Vec<u32>> = Vec::new();
+    for line in reader.lines() {
+        let line_vector: Vec<u32> = line.unwrap().chars().map(|c| c.to_digit(10).unwrap()).collect();
+        data.push(line_vector);
+    }
+    Forest::from(data)
+}
+
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let forest = match args.iter().position(|arg| arg == "--generate") {
+        Some(index) => {
+            let n_rows: usize = args[index + 1].parse().unwrap();
+            let n_cols: usize = args[index + 2].parse().unwrap();
+            Forest::from(generate_heights(n_rows, n_cols, 42))
         }
+        None => read_forest(),
+    };
+    if let Some(index) = args.iter().position(|arg| arg == "--pgm") {
+        std::fs::write(&args[index + 1], forest.scores_pgm()).unwrap();
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--csv") {
+        std::fs::write(&args[index + 1], forest.scores_csv()).unwrap();
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--explain") {
+        let row_ind: usize = args[index + 1].parse().unwrap();
+        let col_ind: usize = args[index + 2].parse().unwrap();
+        print!("{}", forest.explain(row_ind, col_ind));
+        return;
     }
-    forest.collect_last_row();
-    forest.collect_columns();
     println!("Part 1: {}", forest.number_of_visible_trees());
+    println!("Part 2: {}", forest.find_max_fov());
 }
 
-struct SpotLocator {
-    heights: Vec<Vec<u32>>,
-    distances: Vec<Vec<[u32; 4]>>,
-    n_rows: usize,
-    n_cols: usize,
-}
 
-impl SpotLocator{
-    fn from(heights: Vec<Vec<u32>>) -> Self {
-        let n_rows = heights.len();
-        let n_cols = heights[0].len();
-        let distances = vec![vec![[0; 4]; n_cols]; n_rows]; // (left, right, top, bottom)
-        Self { heights, distances, n_rows, n_cols }
+#[cfg(test)]
+mod tests {
+    use super::{generate_heights, Direction, Forest};
+
+    fn example() -> Forest {
+        let text = ["30373", "25512", "65332", "33549", "35390"];
+        Forest::from(text.iter().map(|line| line.chars().map(|c| c.to_digit(10).unwrap()).collect()).collect())
     }
 
-    fn calculate_distances(&mut self) {
-        for row_ind in 0..self.n_rows {
-            for col_ind in 0..self.n_cols {
-                self.update_element(row_ind, col_ind);
-            }
-        }
+    #[test]
+    fn example_answers() {
+        let forest = example();
+        assert_eq!(forest.number_of_visible_trees(), 21);
+        assert_eq!(forest.find_max_fov(), 8);
+    }
 
+    #[test]
+    fn single_trees() {
+        let forest = example();
+        assert_eq!(forest.visible_from(1, 2), vec![Direction::Right, Direction::Top]);
+        assert_eq!(forest.viewing_distances(1, 2), [1, 2, 1, 2]);
+        assert_eq!(forest.scenic_score(3, 2), 8);
+        assert_eq!(forest.viewing_distances(3, 2), [2, 2, 2, 1]);
+        assert!(forest.visible_from(2, 2).is_empty());
     }
 
-    fn update_left_right(&mut self, row_ind: usize, col_ind: usize) {
-        if col_ind == 0 {
-            return
-        }
-        // left
-        self.distances[row_ind][col_ind][0] = 1;
-        let mut aux_ind = 1;
-        while self.heights[row_ind][col_ind] > self.heights[row_ind][col_ind-aux_ind] {
-            aux_ind += 1;
-            if aux_ind > col_ind {
-                break;
-            }
-            self.distances[row_ind][col_ind][0] += 1;
-        }
-        // right
-        aux_ind = 1;
-        let mod_col_ind = self.n_cols - 1 - col_ind;
-        self.distances[row_ind][mod_col_ind][1] = 1;
-        while self.heights[row_ind][mod_col_ind] > self.heights[row_ind][mod_col_ind+aux_ind] {
-            aux_ind += 1;
-            if aux_ind > col_ind {
-                break;
-            }
-            self.distances[row_ind][mod_col_ind][1] += 1;
-        }
+    #[test]
+    fn matrices() {
+        let forest = example();
+        let mask: Vec<String> = forest.visibility_mask().iter()
+            .map(|row| row.iter().map(|&visible| if visible { '#' } else { '.' }).collect())
+            .collect();
+        assert_eq!(mask, vec!["#####", "###.#", "##.##", "#.#.#", "#####"]);
+        assert_eq!(forest.score_matrix()[1], vec![0, 1, 4, 1, 0]);
+        assert_eq!(forest.score_matrix()[3], vec![0, 1, 8, 3, 0]);
     }
 
-    fn update_top_bottom(&mut self, row_ind: usize, col_ind: usize) {
-        if row_ind == 0 {
-            return;
-        }
-        // top
-        self.distances[row_ind][col_ind][2] = 1;
-        let mut aux_ind = 1;
-        while self.heights[row_ind][col_ind] > self.heights[row_ind-aux_ind][col_ind] {
-            aux_ind += 1;
-            if aux_ind > row_ind {
-                break;
+    /// Viewing distance walking from the tree, as in the puzzle statement.
+    fn brute_force_distance(heights: &[Vec<u32>], row_ind: usize, col_ind: usize, step: (isize, isize)) -> (u32, bool) {
+        let height = heights[row_ind][col_ind];
+        let (mut row, mut col) = (row_ind as isize, col_ind as isize);
+        let mut distance = 0;
+        loop {
+            row += step.0;
+            col += step.1;
+            if row < 0 || col < 0 || row as usize >= heights.len() || col as usize >= heights[0].len() {
+                return (distance, true);
             }
-            self.distances[row_ind][col_ind][2] += 1;
-        }
-        // bottom
-        aux_ind = 1;
-        let mod_row_ind = self.n_rows - 1 - row_ind;
-        self.distances[mod_row_ind][col_ind][3] = 1;
-        while self.heights[mod_row_ind][col_ind] > self.heights[mod_row_ind+aux_ind][col_ind] {
-            aux_ind += 1;
-            if aux_ind > row_ind {
-                break;
+            distance += 1;
+            if heights[row as usize][col as usize] >= height {
+                return (distance, false);
             }
-            self.distances[mod_row_ind][col_ind][3] += 1;
         }
     }
 
-    fn update_element(&mut self, row_ind: usize, col_ind: usize) {
-        self.update_left_right(row_ind, col_ind);
-        self.update_top_bottom(row_ind, col_ind);
-    }
-
-    fn find_max_fov(&self) -> u32 {
-        let mut max_prod = 0;
-        for row in self.distances.iter() {
-            for elem in row.iter() {
-                let test = elem.iter().product();
-                max_prod = std::cmp::max(max_prod, test);
+    #[test]
+    fn brute_force() {
+        let heights = generate_heights(17, 23, 7);
+        let forest = Forest::from(heights.clone());
+        let steps = [(0, -1), (0, 1), (-1, 0), (1, 0)];
+        for row_ind in 0..17 {
+            for col_ind in 0..23 {
+                let expected: Vec<(u32, bool)> = steps.iter()
+                    .map(|&step| brute_force_distance(&heights, row_ind, col_ind, step))
+                    .collect();
+                let visible: Vec<Direction> = Direction::ALL.iter().zip(expected.iter())
+                    .filter(|(_, (_, visible))| *visible)
+                    .map(|(direction, _)| *direction)
+                    .collect();
+                assert_eq!(forest.viewing_distances(row_ind, col_ind).to_vec(), expected.iter().map(|(distance, _)| *distance).collect::<Vec<_>>());
+                assert_eq!(forest.visible_from(row_ind, col_ind), visible);
             }
         }
-        max_prod
-    }
-}
-
-fn part2() {
-    let reader = read_file();
-    let mut data: Vec<Vec<u32>> = Vec::new();
-    for line in reader.lines() {
-        let line_vector: Vec<u32> = line.unwrap().chars().map(|c| c.to_digit(10).unwrap()).collect();
-        data.push(line_vector);
     }
-    let mut spot_locator = SpotLocator::from(data);
-    spot_locator.calculate_distances();
-
-    println!("Part 2: {}", spot_locator.find_max_fov());
-}
-
-
-fn main() {
-    part1();
-    part2();
 }
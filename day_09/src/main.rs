@@ -9,84 +9,375 @@ fn read_file() -> BufReader<File> {
 }
 
 
-fn part1() {
-    let reader = read_file();
-    let mut head: [i32; 2] = [0, 0];
-    let mut tail: [i32; 2] = [0, 0];
-    let mut result: HashSet<[i32; 2]> = HashSet::new();
-    result.insert([0, 0]);
-
-    for line in reader.lines() {
-        let line = line.unwrap();
-        let (direction, ammount) = line.split_once(" ").unwrap();
-        let ammount: u32 = ammount.parse().unwrap();
-        let index = if (direction == "L") | (direction == "R") {
-            0
-        } else {
-            1
-        };
-        let magnitude = if (direction == "U") | (direction == "R") {
-            1
-        } else {
-            -1
-        };
-        for _ in 0..ammount {
-            head[index] += magnitude;
-            tail = move_tail(&head, tail);
-            result.insert(tail);
+/// Head movement: a unit step (possibly diagonal) repeated `amount` times.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Instruction {
+    step: [i32; 2],
+    amount: u32,
+}
+
+impl Instruction {
+    /// Parses lines like `R 4` or `UL 2`. Directions combine one horizontal
+    /// and one vertical letter at most.
+    fn parse(line: &str) -> Result<Self, String> {
+        let (direction, amount) = line.split_once(' ').ok_or(format!("Bad instruction: {}", line))?;
+        let amount: u32 = amount.parse().map_err(|_| format!("Bad amount: {}", line))?;
+        let mut step = [0, 0];
+        for c in direction.chars() {
+            let (index, magnitude) = match c {
+                'L' => (0, -1),
+                'R' => (0, 1),
+                'U' => (1, 1),
+                'D' => (1, -1),
+                _ => return Err(format!("Bad direction: {}", line)),
+            };
+            if step[index] != 0 {
+                return Err(format!("Bad direction: {}", line));
+            }
+            step[index] = magnitude;
         }
+        if step == [0, 0] {
+            return Err(format!("Bad direction: {}", line));
+        }
+        Ok(Self {step, amount})
     }
+}
+
 
-    println!("Part 1: {}", result.len());
+/// How a knot moves after the knot in front of it (the leader) has moved.
+trait FollowRule {
+    fn follow(&self, leader: [i32; 2], knot: [i32; 2]) -> [i32; 2];
 }
 
-fn move_tail(head: &[i32; 2], mut tail: [i32; 2]) -> [i32; 2] {
-    let diff_x = head[0] - tail[0];
-    let diff_y = head[1] - tail[1];
-    if (diff_x.abs() <= 1) & (diff_y.abs() <= 1) {
-        return tail;
+
+/// Step one unit (diagonally if needed) towards the leader when it is more
+/// than `max_length` cells away in any axis. With `max_length` 1 it is the
+/// rule of the puzzle.
+struct Chebyshev {
+    max_length: i32,
+}
+
+impl FollowRule for Chebyshev {
+    fn follow(&self, leader: [i32; 2], knot: [i32; 2]) -> [i32; 2] {
+        let diff_x = leader[0] - knot[0];
+        let diff_y = leader[1] - knot[1];
+        if diff_x.abs().max(diff_y.abs()) <= self.max_length {
+            return knot;
+        }
+        [knot[0] + diff_x.signum(), knot[1] + diff_y.signum()]
     }
-    tail[0] += diff_x.signum();
-    tail[1] += diff_y.signum();
-    return tail;
 }
 
-fn part2() {
-    let reader = read_file();
-
-    let mut knots: [[i32; 2]; 10] = [[0, 0]; 10];
-
-    let mut result: HashSet<[i32; 2]> = HashSet::new();
-    result.insert([0, 0]);
-
-    for line in reader.lines() {
-        let line = line.unwrap();
-        let (direction, ammount) = line.split_once(" ").unwrap();
-        let ammount: u32 = ammount.parse().unwrap();
-        let index = if (direction == "L") | (direction == "R") {
-            0
-        } else {
-            1
-        };
-        let magnitude = if (direction == "U") | (direction == "R") {
-            1
-        } else {
-            -1
-        };
-        for _ in 0..ammount {
-            knots[0][index] += magnitude;
-            for i in 1..10 {
-                knots[i] = move_tail(&knots[i-1], knots[i]);
+
+/// Step along the longer axis towards the leader until the Manhattan
+/// distance to it is at most `slack`, so the knot never lands on the leader.
+struct Manhattan {
+    slack: i32,
+}
+
+impl FollowRule for Manhattan {
+    fn follow(&self, leader: [i32; 2], knot: [i32; 2]) -> [i32; 2] {
+        let mut knot = knot;
+        loop {
+            let diff_x = leader[0] - knot[0];
+            let diff_y = leader[1] - knot[1];
+            if diff_x.abs() + diff_y.abs() <= self.slack {
+                return knot;
+            }
+            if diff_x.abs() >= diff_y.abs() {
+                knot[0] += diff_x.signum();
+            } else {
+                knot[1] += diff_y.signum();
+            }
+        }
+    }
+}
+
+
+/// Parses `chebyshev[:N]` or `manhattan[:N]`.
+fn parse_rule(text: &str) -> Result<Box<dyn FollowRule>, String> {
+    let (name, value) = text.split_once(':').unwrap_or((text, "1"));
+    let value: i32 = value.parse().map_err(|_| format!("Bad rule parameter: {}", text))?;
+    if value < 1 {
+        return Err(format!("The rule parameter must be at least 1: {}", text));
+    }
+    match name {
+        "chebyshev" => Ok(Box::new(Chebyshev {max_length: value})),
+        "manhattan" => Ok(Box::new(Manhattan {slack: value})),
+        _ => Err(format!("Unknown rule: {}", text)),
+    }
+}
+
+
+/// Rope with any number of knots. The first one is the head and every knot
+/// keeps the set of positions it has visited.
+struct Rope {
+    knots: Vec<[i32; 2]>,
+    visited: Vec<HashSet<[i32; 2]>>,
+    rule: Box<dyn FollowRule>,
+}
+
+impl Rope {
+    fn new(n_knots: usize) -> Self {
+        Self::with_rule(n_knots, Box::new(Chebyshev {max_length: 1}))
+    }
+
+    fn with_rule(n_knots: usize, rule: Box<dyn FollowRule>) -> Self {
+        assert!(n_knots > 0, "A rope needs at least one knot");
+        Self {knots: vec![[0, 0]; n_knots], visited: vec![HashSet::from([[0, 0]]); n_knots], rule}
+    }
+
+    fn step(&mut self, step: [i32; 2]) {
+        self.knots[0][0] += step[0];
+        self.knots[0][1] += step[1];
+        self.visited[0].insert(self.knots[0]);
+        for i in 1..self.knots.len() {
+            self.knots[i] = self.rule.follow(self.knots[i - 1], self.knots[i]);
+            self.visited[i].insert(self.knots[i]);
+        }
+    }
+
+    fn apply(&mut self, instruction: &Instruction) {
+        for _ in 0..instruction.amount {
+            self.step(instruction.step);
+        }
+    }
+
+    fn visited(&self, knot: usize) -> &HashSet<[i32; 2]> {
+        &self.visited[knot]
+    }
+
+    fn tail(&self) -> usize {
+        self.knots.len() - 1
+    }
+
+    fn knot_char(&self, knot: usize) -> char {
+        match knot {
+            0 => 'H',
+            _ if self.knots.len() == 2 => 'T',
+            1..=9 => char::from_digit(knot as u32, 10).unwrap(),
+            _ => '*',
+        }
+    }
+
+    /// Draws the rope over the trail of `trail_knot` (`#`) and the start
+    /// (`s`) as in the puzzle, with `y` growing upwards.
+    fn render(&self, trail_knot: usize) -> String {
+        let points = self.knots.iter().chain(self.visited[trail_knot].iter());
+        let (mut min, mut max) = ([0, 0], [0, 0]);
+        for point in points {
+            for axis in 0..2 {
+                min[axis] = min[axis].min(point[axis]);
+                max[axis] = max[axis].max(point[axis]);
+            }
+        }
+        let mut text = String::new();
+        for y in (min[1]..=max[1]).rev() {
+            for x in min[0]..=max[0] {
+                let c = match self.knots.iter().position(|knot| *knot == [x, y]) {
+                    Some(knot) => self.knot_char(knot),
+                    None if [x, y] == [0, 0] => 's',
+                    None if self.visited[trail_knot].contains(&[x, y]) => '#',
+                    None => '.',
+                };
+                text.push(c);
             }
-            result.insert(knots[9]);
+            text.push('\n');
+        }
+        text
+    }
+}
+
+
+/// Position of every knot after a single step of the head.
+#[derive(Debug, PartialEq)]
+struct Event {
+    step: usize,
+    instruction: usize,
+    knots: Vec<[i32; 2]>,
+}
+
+impl Event {
+    fn to_json(&self) -> String {
+        let knots: Vec<String> = self.knots.iter().map(|knot| format!("[{},{}]", knot[0], knot[1])).collect();
+        format!("{{\"step\":{},\"instruction\":{},\"knots\":[{}]}}", self.step, self.instruction, knots.join(","))
+    }
+}
+
+
+/// Runs the instructions on a rope yielding an `Event` per step.
+struct Simulation {
+    rope: Rope,
+    instructions: Vec<Instruction>,
+    instruction: usize,
+    done_steps: u32,
+    step: usize,
+}
+
+impl Simulation {
+    fn new(rope: Rope, instructions: Vec<Instruction>) -> Self {
+        Self {rope, instructions, instruction: 0, done_steps: 0, step: 0}
+    }
+}
+
+impl Iterator for Simulation {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        while self.instructions.get(self.instruction)?.amount == self.done_steps {
+            self.instruction += 1;
+            self.done_steps = 0;
         }
+        self.rope.step(self.instructions[self.instruction].step);
+        self.done_steps += 1;
+        self.step += 1;
+        Some(Event {step: self.step, instruction: self.instruction + 1, knots: self.rope.knots.clone()})
     }
+}
+
+
+fn read_instructions() -> Vec<Instruction> {
+    read_file()
+        .lines()
+        .map(|line| Instruction::parse(&line.unwrap()).unwrap())
+        .collect()
+}
+
+
+fn simulate(n_knots: usize) -> Rope {
+    let mut rope = Rope::new(n_knots);
+    for instruction in read_instructions() {
+        rope.apply(&instruction);
+    }
+    rope
+}
+
+
+fn part1() {
+    let rope = simulate(2);
+    println!("Part 1: {}", rope.visited(rope.tail()).len());
+}
+
+
+fn part2() {
+    let rope = simulate(10);
+    println!("Part 2: {}", rope.visited(rope.tail()).len());
+}
 
-    println!("Part 2: {}", result.len());
+
+fn option(args: &[String], name: &str) -> Option<usize> {
+    let index = args.iter().position(|arg| arg == name)?;
+    Some(args[index + 1].parse().unwrap())
 }
 
 
+/// Options that simulate a single rope, of 10 knots unless `--knots` is
+/// given.
+const ROPE_OPTIONS: [&str; 5] = ["--knots", "--render", "--visited", "--rule", "--events"];
+
+
 fn main() {
-    part1();
-    part2();
+    let args: Vec<String> = std::env::args().collect();
+    if !args.iter().any(|arg| ROPE_OPTIONS.contains(&arg.as_str())) {
+        part1();
+        part2();
+        return;
+    }
+    let n_knots = option(&args, "--knots").unwrap_or(10);
+    let mut rope = match args.iter().position(|arg| arg == "--rule") {
+        Some(index) => Rope::with_rule(n_knots, parse_rule(&args[index + 1]).unwrap()),
+        None => Rope::new(n_knots),
+    };
+    if args.iter().any(|arg| arg == "--events") {
+        for event in Simulation::new(rope, read_instructions()) {
+            println!("{}", event.to_json());
+        }
+        return;
+    }
+    let knot = option(&args, "--visited").unwrap_or(rope.tail());
+    if knot > rope.tail() {
+        println!("Knot {} does not exist, the rope has {} knots (0 to {})", knot, n_knots, rope.tail());
+        return;
+    }
+    let render = args.iter().any(|arg| arg == "--render");
+    for (index, instruction) in read_instructions().iter().enumerate() {
+        rope.apply(instruction);
+        if render {
+            println!("== {} ==\n{}", index + 1, rope.render(knot));
+        }
+    }
+    println!("Knot {} visited {} positions", knot, rope.visited(knot).len());
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_rule, Chebyshev, Event, FollowRule, Instruction, Manhattan, Rope, Simulation};
+
+    #[test]
+    fn rules() {
+        assert_eq!(Chebyshev {max_length: 1}.follow([2, 1], [0, 0]), [1, 1]);
+        assert_eq!(Chebyshev {max_length: 2}.follow([2, 1], [0, 0]), [0, 0]);
+        assert_eq!(Manhattan {slack: 1}.follow([1, 1], [0, 0]), [1, 0]);
+        assert_eq!(Manhattan {slack: 1}.follow([1, 2], [0, 0]), [1, 1]);
+        assert_eq!(Manhattan {slack: 1}.follow([2, 1], [0, 0]), [2, 0]);
+        assert_eq!(Manhattan {slack: 2}.follow([1, 1], [0, 0]), [0, 0]);
+        assert_eq!(Manhattan {slack: 2}.follow([3, 2], [0, 0]), [2, 1]);
+        assert!(parse_rule("manhattan:0").is_err());
+        assert!(parse_rule("chebyshev:-1").is_err());
+    }
+
+    fn apply(rope: &mut Rope, lines: &[&str]) {
+        for line in lines {
+            rope.apply(&Instruction::parse(line).unwrap());
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Instruction::parse("R 4"), Ok(Instruction {step: [1, 0], amount: 4}));
+        assert_eq!(Instruction::parse("UL 2"), Ok(Instruction {step: [-1, 1], amount: 2}));
+        assert_eq!(Instruction::parse("DR 1"), Ok(Instruction {step: [1, -1], amount: 1}));
+        assert!(Instruction::parse("LR 1").is_err());
+        assert!(Instruction::parse("UU 1").is_err());
+        assert!(Instruction::parse("X 1").is_err());
+        assert!(Instruction::parse("R x").is_err());
+        assert!(Instruction::parse("R").is_err());
+    }
+
+    #[test]
+    fn larger_example() {
+        let mut rope = Rope::new(10);
+        apply(&mut rope, &["R 5", "U 8", "L 8", "D 3", "R 17", "D 10", "L 25", "U 20"]);
+        assert_eq!(rope.visited(rope.tail()).len(), 36);
+        assert_eq!(rope.knots[0], [-11, 15]);
+    }
+
+    #[test]
+    fn visited_by_any_knot() {
+        let mut rope = Rope::new(3);
+        apply(&mut rope, &["R 4"]);
+        assert_eq!(rope.visited(0).len(), 5);
+        assert_eq!(rope.visited(1).len(), 4);
+        assert_eq!(rope.visited(2).len(), 3);
+        assert!(rope.visited(1).contains(&[3, 0]));
+        assert!(!rope.visited(2).contains(&[3, 0]));
+    }
+
+    #[test]
+    fn render() {
+        let mut rope = Rope::new(2);
+        apply(&mut rope, &["R 4", "U 2"]);
+        assert_eq!(rope.render(rope.tail()), "....H\n....T\ns###.\n");
+        assert_eq!(rope.render(0), "....H\n....T\ns####\n");
+    }
+
+    #[test]
+    fn events() {
+        let instructions = vec![Instruction::parse("R 2").unwrap(), Instruction::parse("UL 1").unwrap()];
+        let events: Vec<Event> = Simulation::new(Rope::new(2), instructions).collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1], Event {step: 2, instruction: 1, knots: vec![[2, 0], [1, 0]]});
+        assert_eq!(events[2].to_json(), "{\"step\":3,\"instruction\":2,\"knots\":[[1,1],[1,0]]}");
+    }
 }
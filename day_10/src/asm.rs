@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::cpu::Instruction;
+
+
+const MAGIC: &[u8; 4] = b"CRT1";
+const OP_NOOP: u8 = 0x00;
+const OP_ADDX_8: u8 = 0x01;
+const OP_ADDX_16: u8 = 0x02;
+
+/// Limits of the assembler, so every program fits in the binary format and
+/// `.repeat` blocks can not exhaust the memory.
+pub const MAX_INSTRUCTIONS: usize = 1 << 20;
+pub const MAX_LABELS: usize = u16::MAX as usize;
+pub const MAX_LABEL_LEN: usize = u8::MAX as usize;
+
+
+/// Assembled program: the instructions and the address of every label.
+#[derive(Debug, Default, PartialEq)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+    pub labels: BTreeMap<String, usize>,
+}
+
+
+/// Error of the assembler or the binary loader. `line` is the 1-based source
+/// line for the assembler and the byte offset for the loader.
+#[derive(Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl AsmError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {line, message: message.into()}
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.line, self.message)
+    }
+}
+
+
+/// Labels are made of alphanumeric characters and `_`.
+fn is_label(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+
+/// Assembles source text. Besides `noop` and `addx N` it accepts comments
+/// (`#` or `;`), labels (`name:`, alone or before an instruction) and
+/// `.repeat N` ... `.end` blocks, which can be nested. Programs are limited
+/// to `MAX_INSTRUCTIONS` instructions and `MAX_LABELS` labels of at most
+/// `MAX_LABEL_LEN` bytes.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut program = Program::default();
+    // Start index in `instructions`, times, line and number of labels
+    // defined before each open block.
+    let mut blocks: Vec<(usize, u32, usize, usize)> = Vec::new();
+    // Labels with their line, in the order they are defined.
+    let mut defined: Vec<(String, usize)> = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let mut code = line.split(['#', ';']).next().unwrap().trim();
+        if let Some((label, rest)) = code.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(AsmError::new(number, format!("invalid label `{}`", label)));
+            }
+            if label.len() > MAX_LABEL_LEN {
+                return Err(AsmError::new(number, format!("label longer than {} bytes", MAX_LABEL_LEN)));
+            }
+            if program.labels.len() == MAX_LABELS {
+                return Err(AsmError::new(number, format!("more than {} labels", MAX_LABELS)));
+            }
+            if program.labels.insert(label.to_string(), program.instructions.len()).is_some() {
+                return Err(AsmError::new(number, format!("duplicated label `{}`", label)));
+            }
+            defined.push((label.to_string(), number));
+            code = rest.trim();
+        }
+        if code.is_empty() {
+            continue;
+        }
+        let words: Vec<&str> = code.split_whitespace().collect();
+        match words.as_slice() {
+            [".repeat", times] => {
+                let times: u32 = times.parse()
+                    .map_err(|_| AsmError::new(number, format!("invalid repeat count `{}`", times)))?;
+                if times as usize > MAX_INSTRUCTIONS {
+                    return Err(AsmError::new(number, format!("repeat count {} larger than {}", times, MAX_INSTRUCTIONS)));
+                }
+                blocks.push((program.instructions.len(), times, number, defined.len()));
+            }
+            [".end"] => {
+                let (start, times, _, n_labels) = blocks.pop()
+                    .ok_or_else(|| AsmError::new(number, "`.end` without `.repeat`"))?;
+                if times == 0 {
+                    if let Some((label, line)) = defined.get(n_labels) {
+                        return Err(AsmError::new(*line, format!("label `{}` inside a block repeated 0 times", label)));
+                    }
+                }
+                let block = program.instructions.split_off(start);
+                if start + block.len() * times as usize > MAX_INSTRUCTIONS {
+                    return Err(AsmError::new(number, format!("more than {} instructions", MAX_INSTRUCTIONS)));
+                }
+                for _ in 0..times {
+                    program.instructions.extend_from_slice(&block);
+                }
+            }
+            ["addx", value] => {
+                let value: i32 = value.parse()
+                    .map_err(|_| AsmError::new(number, format!("invalid operand `{}`", value)))?;
+                if i16::try_from(value).is_err() {
+                    return Err(AsmError::new(number, format!("operand {} out of range", value)));
+                }
+                program.instructions.push(Instruction::Addx(value));
+            }
+            _ => {
+                let instruction = Instruction::parse(code).map_err(|message| AsmError::new(number, message))?;
+                program.instructions.push(instruction);
+            }
+        }
+        if program.instructions.len() > MAX_INSTRUCTIONS {
+            return Err(AsmError::new(number, format!("more than {} instructions", MAX_INSTRUCTIONS)));
+        }
+    }
+    if let Some((_, _, line, _)) = blocks.pop() {
+        return Err(AsmError::new(line, "`.repeat` without `.end`"));
+    }
+    Ok(program)
+}
+
+
+/// Binary format: the magic `CRT1`, the number of instructions (u32), the
+/// instructions (one opcode byte followed by an i8 or i16 operand for
+/// `addx`) and the labels (u16 count, then name length, name and u32
+/// address for each one). Integers are little endian. The program must be
+/// within the limits of `assemble`, which `decode` also guarantees.
+pub fn encode(program: &Program) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend((program.instructions.len() as u32).to_le_bytes());
+    for instruction in program.instructions.iter() {
+        match *instruction {
+            Instruction::Noop => bytes.push(OP_NOOP),
+            Instruction::Addx(value) => match i8::try_from(value) {
+                Ok(value) => bytes.extend([OP_ADDX_8, value as u8]),
+                Err(_) => {
+                    bytes.push(OP_ADDX_16);
+                    bytes.extend((value as i16).to_le_bytes());
+                }
+            },
+        }
+    }
+    bytes.extend((program.labels.len() as u16).to_le_bytes());
+    for (label, address) in program.labels.iter() {
+        bytes.push(label.len() as u8);
+        bytes.extend(label.as_bytes());
+        bytes.extend((*address as u32).to_le_bytes());
+    }
+    bytes
+}
+
+
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+
+/// Cursor over the binary program that reports the offset of bad reads.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], AsmError> {
+        let end = self.offset + len;
+        let chunk = self.bytes.get(self.offset..end)
+            .ok_or_else(|| AsmError::new(self.offset, "unexpected end of program"))?;
+        self.offset = end;
+        Ok(chunk)
+    }
+
+    fn u8(&mut self) -> Result<u8, AsmError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, AsmError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, AsmError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+
+pub fn decode(bytes: &[u8]) -> Result<Program, AsmError> {
+    let mut reader = Reader {bytes, offset: 0};
+    if reader.take(4)? != MAGIC {
+        return Err(AsmError::new(0, "not a program file"));
+    }
+    let mut program = Program::default();
+    let len = reader.u32()?;
+    if len as usize > MAX_INSTRUCTIONS {
+        return Err(AsmError::new(4, format!("more than {} instructions", MAX_INSTRUCTIONS)));
+    }
+    for _ in 0..len {
+        let offset = reader.offset;
+        let instruction = match reader.u8()? {
+            OP_NOOP => Instruction::Noop,
+            OP_ADDX_8 => Instruction::Addx(reader.u8()? as i8 as i32),
+            OP_ADDX_16 => Instruction::Addx(reader.u16()? as i16 as i32),
+            opcode => return Err(AsmError::new(offset, format!("unknown opcode {:#04x}", opcode))),
+        };
+        program.instructions.push(instruction);
+    }
+    for _ in 0..reader.u16()? {
+        let offset = reader.offset;
+        let name_len = reader.u8()? as usize;
+        let name = String::from_utf8(reader.take(name_len)?.to_vec())
+            .map_err(|_| AsmError::new(offset, "label is not valid UTF-8"))?;
+        if !is_label(&name) {
+            return Err(AsmError::new(offset, format!("invalid label `{}`", name)));
+        }
+        let address = reader.u32()? as usize;
+        if address > program.instructions.len() {
+            return Err(AsmError::new(offset, format!("label `{}` past the end of the program", name)));
+        }
+        if program.labels.insert(name.clone(), address).is_some() {
+            return Err(AsmError::new(offset, format!("duplicated label `{}`", name)));
+        }
+    }
+    if reader.offset != bytes.len() {
+        return Err(AsmError::new(reader.offset, "trailing bytes"));
+    }
+    Ok(program)
+}
+
+
+/// Source text of the program, with the labels before their instruction.
+pub fn disassemble(program: &Program) -> String {
+    let mut text = String::new();
+    for address in 0..=program.instructions.len() {
+        for (label, _) in program.labels.iter().filter(|(_, &label_address)| label_address == address) {
+            text += &format!("{}:\n", label);
+        }
+        if let Some(instruction) = program.instructions.get(address) {
+            text += &format!("    {}\n", instruction);
+        }
+    }
+    text
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{assemble, decode, disassemble, encode, AsmError, MAX_INSTRUCTIONS};
+    use super::super::cpu::Instruction;
+
+    #[test]
+    fn round_trip() {
+        let source = "start: noop  # comment\n.repeat 2\n  addx 300\n  .repeat 2\n    addx -1\n  .end\n.end\nend:\n";
+        let program = assemble(source).unwrap();
+        assert_eq!(program.instructions.len(), 7);
+        assert_eq!(program.instructions[1], Instruction::Addx(300));
+        assert_eq!(program.labels["end"], 7);
+        let bytes = encode(&program);
+        assert_eq!(decode(&bytes), Ok(program));
+        let program = decode(&bytes).unwrap();
+        assert_eq!(assemble(&disassemble(&program)), Ok(program));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(assemble("noop\naddx 40000").unwrap_err(), AsmError {line: 2, message: "operand 40000 out of range".to_string()});
+        assert_eq!(assemble(".repeat 2\nnoop").unwrap_err().line, 1);
+        assert_eq!(assemble("a:\na: noop").unwrap_err().line, 2);
+        assert_eq!(assemble("jmp 3").unwrap_err().line, 1);
+        assert_eq!(assemble(".repeat 0\nx: noop\n.end\naddx 1").unwrap_err(),
+                   AsmError {line: 2, message: "label `x` inside a block repeated 0 times".to_string()});
+        assert_eq!(assemble(".repeat 0\n.repeat 2\nnoop\ny:\n.end\n.end").unwrap_err().line, 4);
+        assert_eq!(assemble("x:\n.repeat 0\nnoop\n.end\naddx 1").unwrap().labels["x"], 0);
+        assert_eq!(decode(b"CRT1\x01\x00\x00\x00\x07").unwrap_err().line, 8);
+    }
+
+    #[test]
+    fn limits() {
+        let label = "a".repeat(255);
+        assert_eq!(assemble(&format!("{}: noop", label)).unwrap().labels[&label], 0);
+        assert_eq!(assemble(&format!("noop\n{}a: noop", label)).unwrap_err(),
+                   AsmError {line: 2, message: "label longer than 255 bytes".to_string()});
+        let labels: String = (0..65536).map(|index| format!("l{}:\n", index)).collect();
+        assert_eq!(assemble(&labels).unwrap_err().line, 65536);
+        assert_eq!(assemble(".repeat 4000000000\nnoop\n.end").unwrap_err().line, 1);
+        assert_eq!(assemble(".repeat 1024\n.repeat 1024\nnoop\n.end\n.end").unwrap().instructions.len(), MAX_INSTRUCTIONS);
+        assert_eq!(assemble(".repeat 1024\n.repeat 1024\nnoop\n.end\n.end\nnoop").unwrap_err().line, 6);
+        assert_eq!(assemble("noop\n.repeat 1024\n.repeat 1024\nnoop\n.end\n.end").unwrap_err().line, 6);
+    }
+
+    #[test]
+    fn crafted_binaries() {
+        let header = b"CRT1\x01\x00\x00\x00\x00";
+        let with_label = |label: &[u8]| [&header[..], b"\x01\x00", label].concat();
+        let program = decode(&with_label(b"\x01a\x01\x00\x00\x00")).unwrap();
+        assert_eq!(program.labels["a"], 1);
+        assert_eq!(assemble(&disassemble(&program)), Ok(program));
+        assert_eq!(decode(&with_label(b"\x03a b\x00\x00\x00\x00")).unwrap_err(),
+                   AsmError {line: 11, message: "invalid label `a b`".to_string()});
+        assert_eq!(decode(&with_label(b"\x00\x00\x00\x00\x00")).unwrap_err().line, 11);
+        assert_eq!(decode(&with_label(b"\x02a:\x00\x00\x00\x00")).unwrap_err().line, 11);
+        assert_eq!(decode(&with_label(b"\x01a\x02\x00\x00\x00")).unwrap_err(),
+                   AsmError {line: 11, message: "label `a` past the end of the program".to_string()});
+        let duplicated = [&header[..], b"\x02\x00\x01a\x00\x00\x00\x00\x01a\x01\x00\x00\x00"].concat();
+        assert_eq!(decode(&duplicated).unwrap_err(),
+                   AsmError {line: 17, message: "duplicated label `a`".to_string()});
+        assert_eq!(decode(b"CRT1\xff\xff\xff\xff").unwrap_err().line, 4);
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fmt;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    Noop,
+    Addx(i32),
+}
+
+impl Instruction {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["noop"] => Ok(Instruction::Noop),
+            ["addx", value] => value.parse()
+                .map(Instruction::Addx)
+                .map_err(|_| format!("Bad operand: {}", line)),
+            _ => Err(format!("Unknown instruction: {}", line)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::Noop => "noop",
+            Instruction::Addx(_) => "addx",
+        }
+    }
+
+    /// Number of cycles the instruction takes to complete.
+    pub fn cycles(&self) -> u32 {
+        match self {
+            Instruction::Noop => 1,
+            Instruction::Addx(_) => 2,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Noop => write!(f, "noop"),
+            Instruction::Addx(value) => write!(f, "addx {}", value),
+        }
+    }
+}
+
+
+/// State of the CPU *during* a cycle: `x` has the value it had when the
+/// cycle started, and `instruction` (at address `pc`) is being executed.
+/// `fetched` is true in the first cycle of the instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CycleState {
+    pub cycle: u64,
+    pub x: i32,
+    pub pc: usize,
+    pub instruction: Instruction,
+    pub fetched: bool,
+}
+
+impl CycleState {
+    pub fn signal_strength(&self) -> i64 {
+        self.cycle as i64 * self.x as i64
+    }
+}
+
+impl fmt::Display for CycleState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cycle {:>4}  x {:>4}  pc {:>4}  {}", self.cycle, self.x, self.pc, self.instruction)
+    }
+}
+
+
+/// CPU of the handheld device. Iterating over it runs the program one cycle
+/// at a time.
+pub struct Cpu {
+    program: Vec<Instruction>,
+    pc: usize,
+    x: i32,
+    cycle: u64,
+    busy: u32,
+}
+
+impl Cpu {
+    pub fn new(program: Vec<Instruction>) -> Self {
+        Self {program, pc: 0, x: 1, cycle: 0, busy: 0}
+    }
+}
+
+impl Iterator for Cpu {
+    type Item = CycleState;
+
+    fn next(&mut self) -> Option<CycleState> {
+        let instruction = *self.program.get(self.pc)?;
+        self.cycle += 1;
+        let state = CycleState {cycle: self.cycle, x: self.x, pc: self.pc, instruction, fetched: self.busy == 0};
+        self.busy += 1;
+        if self.busy == instruction.cycles() {
+            if let Instruction::Addx(value) = instruction {
+                self.x += value;
+            }
+            self.pc += 1;
+            self.busy = 0;
+        }
+        Some(state)
+    }
+}
+
+
+/// Signal strength during each of the given cycles, in the same order.
+/// Cycles after the end of the program have no strength.
+pub fn signal_strengths(program: Vec<Instruction>, cycles: &[u64]) -> Vec<(u64, Option<i64>)> {
+    let last = cycles.iter().copied().max().unwrap_or(0);
+    let strengths: HashMap<u64, i64> = Cpu::new(program)
+        .take_while(|state| state.cycle <= last)
+        .map(|state| (state.cycle, state.signal_strength()))
+        .collect();
+    cycles.iter().map(|&cycle| (cycle, strengths.get(&cycle).copied())).collect()
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    Cycle(u64),
+    Address(usize),
+    Opcode(String),
+}
+
+impl Breakpoint {
+    /// Parses `cycle:N`, `pc:N` or `op:NAME`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let error = || format!("Bad breakpoint: {}", text);
+        match text.split_once(':').ok_or_else(error)? {
+            ("cycle", value) => value.parse().map(Breakpoint::Cycle).map_err(|_| error()),
+            ("pc", value) => value.parse().map(Breakpoint::Address).map_err(|_| error()),
+            ("op", name) => Ok(Breakpoint::Opcode(name.to_string())),
+            _ => Err(error()),
+        }
+    }
+
+    fn hits(&self, state: &CycleState) -> bool {
+        match self {
+            Breakpoint::Cycle(cycle) => state.cycle == *cycle,
+            Breakpoint::Address(pc) => state.fetched && state.pc == *pc,
+            Breakpoint::Opcode(name) => state.fetched && state.instruction.name() == name,
+        }
+    }
+}
+
+
+/// Runs the CPU recording every cycle and stopping at breakpoints.
+pub struct Debugger {
+    cpu: Cpu,
+    breakpoints: Vec<Breakpoint>,
+    pub trace: Vec<CycleState>,
+}
+
+impl Debugger {
+    pub fn new(cpu: Cpu, breakpoints: Vec<Breakpoint>) -> Self {
+        Self {cpu, breakpoints, trace: Vec::new()}
+    }
+
+    pub fn step(&mut self) -> Option<CycleState> {
+        let state = self.cpu.next()?;
+        self.trace.push(state);
+        Some(state)
+    }
+
+    /// Runs until a breakpoint is hit, returning the state of that cycle, or
+    /// `None` when the program ends.
+    pub fn resume(&mut self) -> Option<CycleState> {
+        loop {
+            let state = self.step()?;
+            if self.breakpoints.iter().any(|breakpoint| breakpoint.hits(&state)) {
+                return Some(state);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{signal_strengths, Breakpoint, Cpu, Debugger, Instruction};
+
+    fn program() -> Vec<Instruction> {
+        ["noop", "addx 3", "addx -5"].iter().map(|line| Instruction::parse(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn x_during_cycles() {
+        let xs: Vec<i32> = Cpu::new(program()).map(|state| state.x).collect();
+        assert_eq!(xs, vec![1, 1, 1, 4, 4]);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let breakpoints = vec![Breakpoint::parse("pc:1").unwrap(), Breakpoint::parse("cycle:5").unwrap()];
+        let mut debugger = Debugger::new(Cpu::new(program()), breakpoints);
+        assert_eq!(debugger.resume().map(|state| state.cycle), Some(2));
+        assert_eq!(debugger.resume().map(|state| state.cycle), Some(5));
+        assert_eq!(debugger.resume(), None);
+        assert_eq!(debugger.trace.len(), 5);
+    }
+
+    #[test]
+    fn unsorted_samples() {
+        assert_eq!(signal_strengths(program(), &[4, 2, 9, 4, 0]),
+                   vec![(4, Some(16)), (2, Some(2)), (9, None), (4, Some(16)), (0, None)]);
+    }
+}
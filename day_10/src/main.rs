@@ -1,116 +1,126 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::fmt;
+mod asm;
+mod cpu;
+mod screen;
 
+use asm::Program;
+use cpu::{Breakpoint, Cpu, Debugger};
+use screen::{Overflow, Screen};
 
-fn read_file() -> BufReader<File> {
-    let file = File::open("../example.txt").unwrap();
-    let file = File::open("../input.txt").unwrap();
-    BufReader::new(file)
+
+fn program_path() -> String {
+    let args: Vec<String> = std::env::args().collect();
+    match args.iter().position(|arg| arg == "--program") {
+        Some(index) => args[index + 1].clone(),
+        None => "../input.txt".to_string(),
+    }
 }
 
 
-fn part1() {
-    let reader = read_file();
-    let mut result = 0;
-    let mut cycle = 0;
-    let mut value = 1;
-    for line in reader.lines() {
-        let line = line.unwrap();
-        cycle += 1;
-        if ((cycle - 20) % 40) == 0 {
-            result += cycle*value;
-        }
-        if !line.starts_with("noop") {
-            let new_val: i32 = line.split_whitespace()
-                .last().unwrap()
-                .parse().unwrap();
-            cycle += 1;
-            if ((cycle - 20) % 40) == 0 {
-                result += cycle*value;
-            }
-            value += new_val;
-        }
+/// Loads a binary program or assembles a source file (the puzzle input is
+/// valid source).
+fn load_program(path: &str) -> Program {
+    let bytes = std::fs::read(path).unwrap();
+    let program = if asm::is_binary(&bytes) {
+        asm::decode(&bytes)
+    } else {
+        asm::assemble(&String::from_utf8(bytes).unwrap())
+    };
+    match program {
+        Ok(program) => program,
+        Err(error) => panic!("Invalid program {}:{}", path, error),
     }
-    println!("Part 1: {}", result);
 }
 
-struct Screen {
-    pixels: [[bool; 40]; 6],
-    crt: i32,
-    cycle: u32,
+
+fn read_program() -> Program {
+    load_program(&program_path())
 }
 
-impl Screen {
-    fn new() -> Self {
-        let mut result = Self {pixels: [[false; 40]; 6], crt: 1, cycle: 0};
-        result.activate_pixel();
-        result
-    }
 
-    fn add_new_command(&mut self, command: String) {
-        if command.starts_with("noop") {
-            self.noop();
-        } else {
-            let new_val: i32 = command.split_whitespace()
-                .last().unwrap()
-                .parse().unwrap();
-            self.add_value(new_val);
-        }
-    }
+fn part1() {
+    let cycles: Vec<u64> = (20..=220).step_by(40).collect();
+    let result: i64 = cpu::signal_strengths(read_program().instructions, &cycles).iter()
+        .filter_map(|(_, strength)| *strength)
+        .sum();
+    println!("Part 1: {}", result);
+}
 
-    fn noop(&mut self) {
-        self.cycle += 1;
-        self.activate_pixel();
-    }
+fn option(args: &[String], name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    Some(args[index + 1].clone())
+}
 
-    fn add_value(&mut self, value: i32) {
-        self.noop();
-        self.crt += value;
-        self.noop();
-    }
 
-    fn activate_pixel(&mut self) {
-        let col = self.cycle % 40;
-        let row = self.cycle / 40;
-        let diff = (col as i32 - self.crt).abs();
-        if diff <= 1 {
-            self.pixels[row as usize][col as usize] = true;
-        }
-    }
+/// Screen from the `--width`, `--height`, `--sprite` and `--overflow`
+/// options, the puzzle one by default.
+fn build_screen(args: &[String]) -> Screen {
+    let default = |name, value: usize| option(args, name).map_or(value, |value| value.parse().unwrap());
+    let overflow = option(args, "--overflow").map_or(Overflow::Stop, |text| Overflow::parse(&text).unwrap());
+    Screen::new(default("--width", 40), default("--height", 6), default("--sprite", 3), overflow)
 }
 
-impl fmt::Display for Screen {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // Use `self.number` to refer to each positional data point.
-        let mut result = String::new();
-        for row in self.pixels {
-            for col in row {
-                if col {
-                    result.push_str("#");
-                } else {
-                    result.push_str(".");
-                }
-            }
-            result.push_str("\n");
-        }
-        write!(f, "{}", result)
+
+fn part2(args: &[String]) {
+    let mut result = build_screen(args);
+    for state in Cpu::new(read_program().instructions) {
+        result.draw(&state);
+    }
+    match option(args, "--render").as_deref() {
+        Some("unicode") => println!("{}", result.unicode()),
+        Some("pbm") => print!("{}", result.pbm()),
+        _ => println!("{}", result),
     }
 }
 
 
-fn part2() {
-    let reader = read_file();
-    let mut result = Screen::new();
-    for line in reader.lines() {
-        let line = line.unwrap();
-        result.add_new_command(line);
+fn debug(args: &[String]) {
+    let program = read_program();
+    // Labels can be used as addresses: `--break pc:loop`.
+    let resolve = |text: &String| match text.split_once(':') {
+        Some(("pc", label)) if program.labels.contains_key(label) => format!("pc:{}", program.labels[label]),
+        _ => text.clone(),
+    };
+    let breakpoints: Vec<Breakpoint> = args.windows(2)
+        .filter(|pair| pair[0] == "--break")
+        .map(|pair| Breakpoint::parse(&resolve(&pair[1])).unwrap())
+        .collect();
+    let mut debugger = Debugger::new(Cpu::new(program.instructions), breakpoints);
+    while let Some(state) = debugger.resume() {
+        println!("Breakpoint: {}", state);
+    }
+    if args.iter().any(|arg| arg == "--trace") {
+        for state in debugger.trace.iter() {
+            println!("{}", state);
+        }
     }
-    println!("{}", result);
 }
 
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--assemble") {
+        let program = load_program(&args[index + 1]);
+        std::fs::write(&args[index + 2], asm::encode(&program)).unwrap();
+        return;
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--disassemble") {
+        print!("{}", asm::disassemble(&load_program(&args[index + 1])));
+        return;
+    }
+    if args.iter().any(|arg| arg == "--break" || arg == "--trace") {
+        debug(&args);
+        return;
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--sample") {
+        let cycles: Vec<u64> = args[index + 1].split(',').map(|cycle| cycle.parse().unwrap()).collect();
+        for (cycle, strength) in cpu::signal_strengths(read_program().instructions, &cycles) {
+            match strength {
+                Some(strength) => println!("Cycle {}: {}", cycle, strength),
+                None => println!("Cycle {}: after the end of the program", cycle),
+            }
+        }
+        return;
+    }
     part1();
-    part2();
+    part2(&args);
 }
@@ -0,0 +1,157 @@
+use std::fmt;
+
+use super::cpu::CycleState;
+
+
+/// What the CRT does when the beam goes past the last row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    /// Ignore the remaining cycles.
+    Stop,
+    /// Start again from the top row, overwriting the old pixels.
+    Wrap,
+    /// Move every row up and keep drawing on a new bottom row.
+    Scroll,
+}
+
+impl Overflow {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text {
+            "stop" => Ok(Overflow::Stop),
+            "wrap" => Ok(Overflow::Wrap),
+            "scroll" => Ok(Overflow::Scroll),
+            _ => Err(format!("Unknown overflow mode: {}", text)),
+        }
+    }
+}
+
+
+pub struct Screen {
+    width: usize,
+    height: usize,
+    sprite_width: usize,
+    overflow: Overflow,
+    pixels: Vec<Vec<bool>>,
+    scrolled: usize,
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize, sprite_width: usize, overflow: Overflow) -> Self {
+        assert!(width > 0 && height > 0, "The screen needs at least one pixel");
+        Self {width, height, sprite_width, overflow, pixels: vec![vec![false; width]; height], scrolled: 0}
+    }
+
+    /// Whether the sprite, centered at `x`, covers the column. Even widths
+    /// have one more pixel at the right of `x`.
+    fn sprite_covers(&self, x: i32, col: usize) -> bool {
+        let start = x as i64 - (self.sprite_width as i64 - 1) / 2;
+        let col = col as i64;
+        start <= col && col < start + self.sprite_width as i64
+    }
+
+    /// Draws the pixel of the cycle, lit if the sprite covers it.
+    pub fn draw(&mut self, state: &CycleState) {
+        let position = (state.cycle - 1) as usize;
+        let col = position % self.width;
+        let mut row = position / self.width;
+        if row >= self.height {
+            match self.overflow {
+                Overflow::Stop => return,
+                Overflow::Wrap => row %= self.height,
+                Overflow::Scroll => {
+                    while row - self.scrolled >= self.height {
+                        self.pixels.remove(0);
+                        self.pixels.push(vec![false; self.width]);
+                        self.scrolled += 1;
+                    }
+                    row -= self.scrolled;
+                }
+            }
+        }
+        self.pixels[row][col] = self.sprite_covers(state.x, col);
+    }
+
+    pub fn ascii(&self) -> String {
+        let mut result = String::new();
+        for row in self.pixels.iter() {
+            for &col in row {
+                if col {
+                    result.push('#');
+                } else {
+                    result.push('.');
+                }
+            }
+            result.push('\n');
+        }
+        result
+    }
+
+    /// Two rows per line with the half block characters.
+    pub fn unicode(&self) -> String {
+        let mut result = String::new();
+        for rows in self.pixels.chunks(2) {
+            for col in 0..self.width {
+                let top = rows[0][col];
+                let bottom = rows.get(1).is_some_and(|row| row[col]);
+                result.push(match (top, bottom) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            result.push('\n');
+        }
+        result
+    }
+
+    /// Plain PBM (P1) image, lit pixels are black.
+    pub fn pbm(&self) -> String {
+        let mut result = format!("P1\n{} {}\n", self.width, self.height);
+        for row in self.pixels.iter() {
+            let values: Vec<&str> = row.iter().map(|&col| if col { "1" } else { "0" }).collect();
+            result += &values.join(" ");
+            result.push('\n');
+        }
+        result
+    }
+}
+
+impl fmt::Display for Screen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.ascii())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Overflow, Screen};
+    use super::super::cpu::{CycleState, Instruction};
+
+    fn state(cycle: u64, x: i32) -> CycleState {
+        CycleState {cycle, x, pc: 0, instruction: Instruction::Noop, fetched: true}
+    }
+
+    #[test]
+    fn overflow() {
+        let mut screen = Screen::new(2, 2, 1, Overflow::Stop);
+        for cycle in 1..=6 {
+            screen.draw(&state(cycle, 0));
+        }
+        assert_eq!(screen.ascii(), "#.\n#.\n");
+
+        let mut screen = Screen::new(2, 2, 1, Overflow::Wrap);
+        for cycle in 1..=6 {
+            screen.draw(&state(cycle, if cycle <= 4 { 0 } else { 1 }));
+        }
+        assert_eq!(screen.ascii(), ".#\n#.\n");
+
+        let mut screen = Screen::new(2, 2, 2, Overflow::Scroll);
+        for cycle in 1..=5 {
+            screen.draw(&state(cycle, if cycle == 5 { 0 } else { 5 }));
+        }
+        assert_eq!(screen.ascii(), "..\n#.\n");
+        assert_eq!(screen.unicode(), "▄ \n");
+    }
+}
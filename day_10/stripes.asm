@@ -0,0 +1,16 @@
+# Draws vertical stripes of three pixels: the sprite jumps 6 columns every
+# 6 cycles, so it is lit for 3 of every 6 pixels (the row ends after the
+# seventh stripe, which only has one dark pixel).
+start:
+.repeat 6           ; one iteration per row
+    .repeat 6
+        noop
+        noop
+        noop
+        noop
+        addx 6
+    .end
+    noop
+    noop
+    addx -36        ; back to the left edge, after drawing the last stripe
+.end
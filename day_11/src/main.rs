@@ -1,29 +1,79 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::collections::HashMap;
 
+use num_bigint::BigUint;
 
-struct Item {
-    worry_level: u64,
-    worry_level_reminders: HashMap<u64, u64>,
+
+/// Representation of the worry level of an item.
+trait Worry: Sized + fmt::Display {
+    /// Worry level after the monkey inspects the item and, if `relax`, after
+    /// dividing it by 3.
+    fn inspect(&self, operation: &Expr, relax: bool) -> Result<Self, String>;
+    fn divisible_by(&self, divisor: u64) -> bool;
+}
+
+/// Exact worry level, the simulation fails if it overflows.
+impl Worry for u64 {
+    fn inspect(&self, operation: &Expr, relax: bool) -> Result<Self, String> {
+        let value = operation.eval(*self)
+            .ok_or_else(|| format!("Worry level out of range applying {:?} to {}", operation, self))?;
+        Ok(if relax { value / 3 } else { value })
+    }
+
+    fn divisible_by(&self, divisor: u64) -> bool {
+        self.is_multiple_of(divisor)
+    }
+}
+
+/// Exact worry level of any size, for validating the other modes.
+impl Worry for BigUint {
+    fn inspect(&self, operation: &Expr, relax: bool) -> Result<Self, String> {
+        let value = operation.eval_big(self)
+            .ok_or_else(|| format!("Negative worry level applying {:?} to {}", operation, self))?;
+        Ok(if relax { value / 3u32 } else { value })
+    }
+
+    fn divisible_by(&self, divisor: u64) -> bool {
+        (self % divisor) == BigUint::ZERO
+    }
 }
 
-impl Item {
-    fn new(worry_level: u64) -> Self {
-        Self { worry_level, worry_level_reminders: HashMap::new() }
+/// Worry level modulo a multiple of every divisor, which keeps the results
+/// of the tests but can not be divided.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Residue {
+    value: u64,
+    modulus: u64,
+}
+
+impl Residue {
+    fn new(value: u64, modulus: u64) -> Self {
+        Self {value: value % modulus, modulus}
     }
+}
 
-    fn set_reminders(&mut self, check_values: &Vec<u64>) {
-        for value in check_values {
-            self.worry_level_reminders.insert(*value, self.worry_level % value);
+impl Worry for Residue {
+    fn inspect(&self, operation: &Expr, relax: bool) -> Result<Self, String> {
+        if relax {
+            return Err("Residues can not be relaxed".to_string());
         }
+        Ok(Self {value: operation.eval_mod(self.value, self.modulus), modulus: self.modulus})
+    }
+
+    fn divisible_by(&self, divisor: u64) -> bool {
+        self.value.is_multiple_of(divisor)
     }
+}
 
-    fn relax(&mut self) {
-        self.worry_level = self.worry_level / 3;
+impl fmt::Display for Residue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (mod {})", self.value, self.modulus)
     }
 }
 
+
 struct Throw {
     divissible_by: u64,
     monkey_id_true: usize,
@@ -35,13 +85,8 @@ impl Throw {
         Self {divissible_by: 1, monkey_id_true: 0, monkey_id_false: 0}
     }
 
-    fn to_monkey(&self, item: &Item, real: bool) -> usize {
-        let reminder = if real {
-            item.worry_level % self.divissible_by as u64
-        } else {
-            *item.worry_level_reminders.get(&(*&self.divissible_by as u64)).unwrap()
-        };
-        if reminder == 0 {
+    fn to_monkey(&self, item: &impl Worry) -> usize {
+        if item.divisible_by(self.divissible_by) {
             self.monkey_id_true
         } else {
             self.monkey_id_false
@@ -49,104 +94,280 @@ impl Throw {
     }
 }
 
-struct Operation {
-    operand: usize,
-    second_term: Option<u64>,
+/// Arithmetic expression of a monkey operation over the old worry level.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Old,
+    Const(u64),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
 }
 
-impl Operation {
-    fn new() -> Self {
-        Self { operand: 0, second_term: None }
+impl Expr {
+    /// Parses expressions with `+`, `-`, `*`, parentheses, constants and
+    /// `old`. `*` binds tighter than `+` and `-`, all are left associative.
+    fn parse(text: &str) -> Result<Self, String> {
+        let tokens = tokenize(text)?;
+        let mut position = 0;
+        let expr = Self::parse_sum(&tokens, &mut position)?;
+        if position != tokens.len() {
+            return Err(format!("Unexpected `{}` in `{}`", tokens[position], text));
+        }
+        Ok(expr)
     }
 
-    fn from(operand: &str, second_term: &str) -> Self {
-        let operand = if operand == "+" {
-            0
-        } else if operand == "*" {
-            1
-        } else {
-            panic!("Error")
-        };
-        let second_term = if second_term == "old" {
-            None
-        } else {
-            Some(second_term.parse().unwrap())
-        };
-        Self {operand, second_term}
+    fn parse_sum(tokens: &[String], position: &mut usize) -> Result<Self, String> {
+        let mut expr = Self::parse_product(tokens, position)?;
+        while let Some(op) = tokens.get(*position).filter(|op| *op == "+" || *op == "-") {
+            *position += 1;
+            let right = Box::new(Self::parse_product(tokens, position)?);
+            expr = if op == "+" { Expr::Add(Box::new(expr), right) } else { Expr::Sub(Box::new(expr), right) };
+        }
+        Ok(expr)
     }
 
-    fn apply(&self, reminders: &mut HashMap<u64, u64>) {
-        for (k, v) in reminders.iter_mut() {
-            *v = match self.second_term {
-                None => {
-                    ((*v)*(*v)) % k
-                }
-                Some(s) => {
-                    if self.operand == 0 {
-                        ((s % k) + (*v)) % k
-                    } else {
-                        ((s % k) * (*v)) % k
-                    }
+    fn parse_product(tokens: &[String], position: &mut usize) -> Result<Self, String> {
+        let mut expr = Self::parse_term(tokens, position)?;
+        while tokens.get(*position).is_some_and(|op| op == "*") {
+            *position += 1;
+            expr = Expr::Mul(Box::new(expr), Box::new(Self::parse_term(tokens, position)?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_term(tokens: &[String], position: &mut usize) -> Result<Self, String> {
+        let token = tokens.get(*position).ok_or("Unexpected end of expression")?;
+        *position += 1;
+        match token.as_str() {
+            "old" => Ok(Expr::Old),
+            "(" => {
+                let expr = Self::parse_sum(tokens, position)?;
+                if tokens.get(*position).is_none_or(|token| token != ")") {
+                    return Err("Missing `)`".to_string());
                 }
-            };
+                *position += 1;
+                Ok(expr)
+            }
+            _ => token.parse().map(Expr::Const).map_err(|_| format!("Unexpected `{}`", token)),
         }
     }
 
-    fn apply_real(&self, first_term: u64) -> u64 {
-        match self.second_term {
-            None => {
-                first_term * first_term
+    /// Value for the given old worry level, `None` if it overflows or is
+    /// negative.
+    fn eval(&self, old: u64) -> Option<u64> {
+        match self {
+            Expr::Old => Some(old),
+            Expr::Const(value) => Some(*value),
+            Expr::Add(left, right) => left.eval(old)?.checked_add(right.eval(old)?),
+            Expr::Sub(left, right) => left.eval(old)?.checked_sub(right.eval(old)?),
+            Expr::Mul(left, right) => left.eval(old)?.checked_mul(right.eval(old)?),
+        }
+    }
+
+    /// Value modulo `modulus` given the old worry level modulo `modulus`.
+    fn eval_mod(&self, old: u64, modulus: u64) -> u64 {
+        let modulus_wide = modulus as u128;
+        match self {
+            Expr::Old => old % modulus,
+            Expr::Const(value) => value % modulus,
+            Expr::Add(left, right) => {
+                ((left.eval_mod(old, modulus) as u128 + right.eval_mod(old, modulus) as u128) % modulus_wide) as u64
+            }
+            Expr::Sub(left, right) => {
+                let right = right.eval_mod(old, modulus) as u128;
+                ((left.eval_mod(old, modulus) as u128 + modulus_wide - right) % modulus_wide) as u64
             }
-            Some(s) => {
-                if self.operand == 0 {
-                    first_term + s
-                } else {
-                    first_term * s
+            Expr::Mul(left, right) => {
+                ((left.eval_mod(old, modulus) as u128 * right.eval_mod(old, modulus) as u128) % modulus_wide) as u64
+            }
+        }
+    }
+
+    /// Exact value for the given old worry level, `None` if it is negative.
+    fn eval_big(&self, old: &BigUint) -> Option<BigUint> {
+        match self {
+            Expr::Old => Some(old.clone()),
+            Expr::Const(value) => Some(BigUint::from(*value)),
+            Expr::Add(left, right) => Some(left.eval_big(old)? + right.eval_big(old)?),
+            Expr::Sub(left, right) => {
+                let (left, right) = (left.eval_big(old)?, right.eval_big(old)?);
+                if left < right { None } else { Some(left - right) }
+            }
+            Expr::Mul(left, right) => Some(left.eval_big(old)? * right.eval_big(old)?),
+        }
+    }
+}
+
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' => {}
+            '+' | '-' | '*' | '(' | ')' => tokens.push(c.to_string()),
+            c if c.is_ascii_alphanumeric() => {
+                let mut word = c.to_string();
+                while let Some(next) = chars.next_if(|next| next.is_ascii_alphanumeric()) {
+                    word.push(next);
                 }
+                tokens.push(word);
             }
+            _ => return Err(format!("Unexpected `{}` in `{}`", c, text)),
         }
     }
+    Ok(tokens)
 }
 
-struct Monkey {
-    items: Vec<Item>,
-    operation: Operation,
+
+
+struct Monkey<W> {
+    items: VecDeque<W>,
+    operation: Expr,
     test_throw: Throw,
     inspected_items: u64,
 }
 
-impl Monkey {
+impl<W: Worry> Monkey<W> {
 
     fn new() -> Self {
-        Self {items: Vec::new(), operation: Operation::new(), test_throw: Throw::new(), inspected_items: 0}
+        Self {items: VecDeque::new(), operation: Expr::Old, test_throw: Throw::new(), inspected_items: 0}
     }
 
-    fn process_item(&mut self, relax: bool) -> Option<(usize, Item)> {
-        let mut item = self.items.pop()?;
-        if relax {
-            item.worry_level = self.operation.apply_real(item.worry_level);
-            item.relax();
-        } else {
-            self.operation.apply(&mut item.worry_level_reminders);
+    /// Same monkey with every worry level converted.
+    fn map_items<V>(self, f: impl Fn(W) -> V) -> Monkey<V> {
+        Monkey {
+            items: self.items.into_iter().map(f).collect(),
+            operation: self.operation,
+            test_throw: self.test_throw,
+            inspected_items: self.inspected_items,
         }
-        let to_monkey = self.test_throw.to_monkey(&item, relax);
+    }
+
+    fn process_item(&mut self, relax: bool, round: usize, from: usize) -> Option<ThrowEvent<W>> {
+        let before = self.items.pop_front()?;
+        let after = match before.inspect(&self.operation, relax) {
+            Ok(item) => item,
+            Err(error) => panic!("{}", error),
+        };
+        let passed = after.divisible_by(self.test_throw.divissible_by);
+        let to = self.test_throw.to_monkey(&after);
         self.inspected_items += 1;
-        Some((to_monkey, item))
+        Some(ThrowEvent {round, from, to, before, after, passed})
+    }
+}
+
+
+/// An item thrown during `round`, `passed` is the result of the test.
+#[derive(Debug, Clone, PartialEq)]
+struct ThrowEvent<W> {
+    round: usize,
+    from: usize,
+    to: usize,
+    before: W,
+    after: W,
+    passed: bool,
+}
+
+impl<W: Worry> fmt::Display for ThrowEvent<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let outcome = if self.passed { "passed" } else { "failed" };
+        write!(f, "Monkey {} threw {} to monkey {} (was {}, test {})", self.from, self.after, self.to, self.before, outcome)
+    }
+}
+
+
+/// Hook called by the simulation, rounds start at 1.
+trait Telemetry<W> {
+    fn throw(&mut self, event: &ThrowEvent<W>);
+    fn round_end(&mut self, _round: usize, _monkeys: &[Monkey<W>]) {}
+}
+
+impl<W> Telemetry<W> for () {
+    fn throw(&mut self, _event: &ThrowEvent<W>) {}
+}
+
+
+/// Counts the throws between each pair of monkeys and writes the puzzle
+/// summaries, with the throws if `log_throws`, after the chosen rounds.
+struct Recorder {
+    rounds: Vec<usize>,
+    relax: bool,
+    log_throws: bool,
+    throws: BTreeMap<(usize, usize), u64>,
+    round_log: Vec<String>,
+    report: String,
+}
+
+impl Recorder {
+    fn new(rounds: Vec<usize>, relax: bool, log_throws: bool) -> Self {
+        Self {rounds, relax, log_throws, throws: BTreeMap::new(), round_log: Vec::new(), report: String::new()}
+    }
+
+    /// Throw graph in Graphviz format, edges labelled with the number of
+    /// throws.
+    fn dot<W>(&self, monkeys: &[Monkey<W>]) -> String {
+        let mut result = "digraph throws {\n".to_string();
+        for (id, monkey) in monkeys.iter().enumerate() {
+            result += &format!("    {} [label=\"Monkey {}\\n{} inspections\"];\n", id, id, monkey.inspected_items);
+        }
+        for ((from, to), count) in self.throws.iter() {
+            result += &format!("    {} -> {} [label=\"{}\"];\n", from, to, count);
+        }
+        result += "}\n";
+        result
     }
 }
 
+impl<W: Worry> Telemetry<W> for Recorder {
+    fn throw(&mut self, event: &ThrowEvent<W>) {
+        *self.throws.entry((event.from, event.to)).or_insert(0) += 1;
+        if self.log_throws && self.rounds.contains(&event.round) {
+            self.round_log.push(event.to_string());
+        }
+    }
+
+    fn round_end(&mut self, round: usize, monkeys: &[Monkey<W>]) {
+        if !self.rounds.contains(&round) {
+            return;
+        }
+        for line in self.round_log.drain(..) {
+            self.report += &line;
+            self.report.push('\n');
+        }
+        if self.relax {
+            self.report += &format!("After round {}, the monkeys are holding items with these worry levels:\n", round);
+            for (id, monkey) in monkeys.iter().enumerate() {
+                let items: Vec<String> = monkey.items.iter().map(|item| item.to_string()).collect();
+                self.report += &format!("Monkey {}: {}\n", id, items.join(", "));
+            }
+        } else {
+            self.report += &format!("== After round {} ==\n", round);
+            for (id, monkey) in monkeys.iter().enumerate() {
+                self.report += &format!("Monkey {} inspected items {} times.\n", id, monkey.inspected_items);
+            }
+        }
+        self.report.push('\n');
+    }
+}
 
-fn iterate_monkeys(mut monkeys: Vec<Monkey>, cycles: usize, relax: bool) -> Vec<Monkey> {
-    for _ in 0..cycles {
+
+fn iterate_monkeys<W: Worry>(monkeys: Vec<Monkey<W>>, cycles: usize, relax: bool) -> Vec<Monkey<W>> {
+    iterate_monkeys_with(monkeys, cycles, relax, &mut ())
+}
+
+fn iterate_monkeys_with<W: Worry>(
+    mut monkeys: Vec<Monkey<W>>, cycles: usize, relax: bool, telemetry: &mut impl Telemetry<W>,
+) -> Vec<Monkey<W>> {
+    for round in 1..=cycles {
         for monkey_ind in 0..monkeys.len() {
-            loop {
-                let (monkey_to, item) = match monkeys[monkey_ind].process_item(relax) {
-                    None => break,
-                    Some(a) => a,
-                };
-                monkeys[monkey_to].items.push(item);
+            while let Some(event) = monkeys[monkey_ind].process_item(relax, round, monkey_ind) {
+                telemetry.throw(&event);
+                monkeys[event.to].items.push_back(event.after);
             }
         }
+        telemetry.round_end(round, &monkeys);
     }
     monkeys
 }
@@ -157,8 +378,11 @@ fn read_file() -> BufReader<File> {
     BufReader::new(file)
 }
 
-fn load_monkeys() -> Vec<Monkey> {
-    let reader = read_file();
+fn load_monkeys() -> Vec<Monkey<u64>> {
+    parse_monkeys(read_file())
+}
+
+fn parse_monkeys(reader: impl BufRead) -> Vec<Monkey<u64>> {
     let mut monkeys = vec![Monkey::new()];
     for line in reader.lines() {
         let line = line.unwrap();
@@ -168,22 +392,20 @@ fn load_monkeys() -> Vec<Monkey> {
         }
         let line_trim = line.trim();
         if line_trim.starts_with("Starting") {
-            let mut items: Vec<_> = line_trim
+            let items: VecDeque<u64> = line_trim
                 .split_once(":").unwrap().1
                 .split(",")
-                .map(|x| Item::new(x.trim().parse().unwrap()))
+                .map(|x| x.trim().parse().unwrap())
                 .collect();
-            items.reverse();
             monkeys.last_mut().unwrap().items = items;
         } else if line_trim.starts_with("Monkey") {
             continue;
         } else if line_trim.starts_with("Operation") {
-            let terms = line_trim.split_once("=").unwrap().1
-                .trim()
-                .split_whitespace()
-                .collect::<Vec<_>>();
-            // first term is always old
-            monkeys.last_mut().unwrap().operation = Operation::from(terms[1], terms[2]);
+            let expression = line_trim.split_once('=').unwrap().1;
+            monkeys.last_mut().unwrap().operation = match Expr::parse(expression) {
+                Ok(operation) => operation,
+                Err(error) => panic!("Invalid operation `{}`: {}", expression, error),
+            };
         } else if line_trim.starts_with("Test") {
             monkeys.last_mut().unwrap().test_throw.divissible_by = line_trim
                 .split_whitespace().last().unwrap().parse().unwrap();
@@ -197,22 +419,42 @@ fn load_monkeys() -> Vec<Monkey> {
             panic!("Wrong line");
         }
     }
-    let values: Vec<_> = monkeys.iter()
-        .map(|m| m.test_throw.divissible_by)
-        .collect();
-    for monkey in monkeys.iter_mut() {
-        for item in monkey.items.iter_mut() {
-            item.set_reminders(&values);
-        }
-    }
     monkeys
 }
 
-fn get_result(monkeys: Vec<Monkey>) -> u64 {
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Least common multiple of the divisors of the tests, `None` if it does not
+/// fit in a `u64`.
+fn common_modulus<W>(monkeys: &[Monkey<W>]) -> Option<u64> {
+    monkeys.iter().try_fold(1u64, |lcm, monkey| {
+        let divisor = monkey.test_throw.divissible_by;
+        (lcm / gcd(lcm, divisor)).checked_mul(divisor)
+    })
+}
+
+fn to_residues(monkeys: Vec<Monkey<u64>>) -> Vec<Monkey<Residue>> {
+    let modulus = common_modulus(&monkeys).expect("The divisors have no common multiple in range");
+    monkeys.into_iter().map(|monkey| monkey.map_items(|item| Residue::new(item, modulus))).collect()
+}
+
+fn to_big(monkeys: Vec<Monkey<u64>>) -> Vec<Monkey<BigUint>> {
+    monkeys.into_iter().map(|monkey| monkey.map_items(BigUint::from)).collect()
+}
+
+/// Product of the inspections of the `top` most active monkeys.
+fn monkey_business<W>(monkeys: &[Monkey<W>], top: usize) -> BigUint {
     let mut inspected_items: Vec<u64> = monkeys.iter().map(|m| m.inspected_items).collect();
     inspected_items.sort();
     inspected_items.reverse();
-    inspected_items[0] as u64 * inspected_items[1] as u64
+    inspected_items.iter().take(top).map(|&count| BigUint::from(count)).product()
+}
+
+fn get_result<W>(monkeys: Vec<Monkey<W>>) -> BigUint {
+    monkey_business(&monkeys, 2)
 }
 
 
@@ -224,14 +466,145 @@ fn part1() {
 }
 
 fn part2() {
-    let mut monkeys = load_monkeys();
-    monkeys = iterate_monkeys(monkeys, 10000, false);
-    let result = get_result(monkeys);
+    let monkeys = to_residues(load_monkeys());
+    let result = get_result(iterate_monkeys(monkeys, 10000, false));
     println!("Part 2: {}", result);
 }
 
 
+fn option(args: &[String], name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    Some(args[index + 1].clone())
+}
+
+
+/// Runs one part with a `Recorder`, printing the summaries of the `--summary`
+/// rounds, the business of the `--top` monkeys and writing the `--dot` graph.
+fn inspect_part<W: Worry>(monkeys: Vec<Monkey<W>>, cycles: usize, relax: bool, args: &[String]) {
+    let rounds = option(args, "--summary")
+        .map_or(Vec::new(), |rounds| rounds.split(',').map(|round| round.parse().unwrap()).collect());
+    let mut recorder = Recorder::new(rounds, relax, args.iter().any(|arg| arg == "--throws"));
+    let monkeys = iterate_monkeys_with(monkeys, cycles, relax, &mut recorder);
+    print!("{}", recorder.report);
+    let top = option(args, "--top").map_or(2, |top| top.parse().unwrap());
+    println!("Monkey business of the top {}: {}", top, monkey_business(&monkeys, top));
+    if let Some(path) = option(args, "--dot") {
+        std::fs::write(path, recorder.dot(&monkeys)).unwrap();
+    }
+}
+
+
+/// Runs both parts for `rounds` rounds with exact worry levels, comparing
+/// part 2 with the residues.
+fn validate(rounds: usize) {
+    let exact = get_result(iterate_monkeys(to_big(load_monkeys()), rounds, true));
+    println!("Relaxed, {} rounds: {}", rounds, exact);
+    let exact = get_result(iterate_monkeys(to_big(load_monkeys()), rounds, false));
+    let fast = get_result(iterate_monkeys(to_residues(load_monkeys()), rounds, false));
+    println!("Worried, {} rounds: {} (residues: {})", rounds, exact, fast);
+}
+
+
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--bigint") {
+        let rounds = args.get(index + 1).map_or(20, |rounds| rounds.parse().unwrap());
+        validate(rounds);
+        return;
+    }
+    if ["--summary", "--top", "--dot"].iter().any(|flag| args.iter().any(|arg| arg == flag)) {
+        match option(&args, "--part").as_deref() {
+            Some("1") => inspect_part(load_monkeys(), 20, true, &args),
+            _ => inspect_part(to_residues(load_monkeys()), 10000, false, &args),
+        }
+        return;
+    }
     part1();
     part2();
 }
+
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigUint;
+
+    use super::{iterate_monkeys, iterate_monkeys_with, monkey_business, parse_monkeys, to_residues};
+    use super::{Expr, Recorder, Residue, Worry};
+
+    #[test]
+    fn expressions() {
+        let expr = Expr::parse("old * 19").unwrap();
+        assert_eq!(expr, Expr::Mul(Box::new(Expr::Old), Box::new(Expr::Const(19))));
+        assert_eq!(expr.eval(2), Some(38));
+        let expr = Expr::parse("(3 + old) * (old - 1) - 2 * old").unwrap();
+        assert_eq!(expr.eval(5), Some(22));
+        assert_eq!(expr.eval_mod(5, 7), 1);
+        assert_eq!(Expr::parse("1 - old").unwrap().eval(2), None);
+        assert_eq!(Expr::parse("1 - old").unwrap().eval_mod(2, 5), 4);
+        assert_eq!(Expr::parse("old * old - 1").unwrap().eval_big(&BigUint::from(u64::MAX)),
+                   Some(BigUint::from(u64::MAX) * u64::MAX - 1u32));
+        assert!(Expr::parse("(old + 1").is_err());
+        assert!(Expr::parse("old / 2").is_err());
+    }
+
+    #[test]
+    fn residues() {
+        let operation = Expr::parse("old * old").unwrap();
+        let residue = Residue::new(u64::MAX - 1, 23 * 19);
+        let exact = BigUint::from(u64::MAX - 1);
+        let (residue, exact) = (residue.inspect(&operation, false).unwrap(), exact.inspect(&operation, false).unwrap());
+        assert_eq!(BigUint::from(residue.value), exact % (23u32 * 19));
+        assert!(residue.inspect(&operation, true).is_err());
+        assert!((u64::MAX - 1).inspect(&operation, false).is_err());
+    }
+
+    const EXAMPLE: &str = "Monkey 0:
+  Starting items: 79, 98
+  Operation: new = old * 19
+  Test: divisible by 23
+    If true: throw to monkey 2
+    If false: throw to monkey 3
+
+Monkey 1:
+  Starting items: 54, 65, 75, 74
+  Operation: new = old + 6
+  Test: divisible by 19
+    If true: throw to monkey 2
+    If false: throw to monkey 0
+
+Monkey 2:
+  Starting items: 79, 60, 97
+  Operation: new = old * old
+  Test: divisible by 13
+    If true: throw to monkey 1
+    If false: throw to monkey 3
+
+Monkey 3:
+  Starting items: 74
+  Operation: new = old + 3
+  Test: divisible by 17
+    If true: throw to monkey 0
+    If false: throw to monkey 1";
+
+    #[test]
+    fn example() {
+        let monkeys = iterate_monkeys(parse_monkeys(EXAMPLE.as_bytes()), 20, true);
+        assert_eq!(monkey_business(&monkeys, 2), BigUint::from(10605u32));
+        let monkeys = iterate_monkeys(to_residues(parse_monkeys(EXAMPLE.as_bytes())), 10000, false);
+        assert_eq!(monkey_business(&monkeys, 2), BigUint::from(2713310158u64));
+    }
+
+    #[test]
+    fn telemetry() {
+        let mut recorder = Recorder::new(vec![1], true, true);
+        let monkeys = iterate_monkeys_with(parse_monkeys(EXAMPLE.as_bytes()), 1, true, &mut recorder);
+        assert!(recorder.report.starts_with("Monkey 0 threw 500 to monkey 3 (was 79, test failed)\nMonkey 0 threw 620 to monkey 3 (was 98, test failed)\n"));
+        assert!(recorder.report.ends_with("Monkey 0: 20, 23, 27, 26\nMonkey 1: 2080, 25, 167, 207, 401, 1046\nMonkey 2: \nMonkey 3: \n\n"));
+        assert_eq!(recorder.throws[&(0, 3)], 2);
+        assert!(recorder.dot(&monkeys).contains("    3 -> 1 [label=\"5\"];\n"));
+
+        let mut recorder = Recorder::new(vec![20], false, false);
+        iterate_monkeys_with(to_residues(parse_monkeys(EXAMPLE.as_bytes())), 20, false, &mut recorder);
+        assert_eq!(recorder.report, "== After round 20 ==\nMonkey 0 inspected items 99 times.\nMonkey 1 inspected items 97 times.\nMonkey 2 inspected items 8 times.\nMonkey 3 inspected items 103 times.\n\n");
+    }
+}
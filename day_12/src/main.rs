@@ -1,12 +1,34 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, VecDeque};
 
 
-const CHARS_INDEXES: [char; 26] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i',
-    'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x',
-    'y', 'z'];
+/// Which steps are allowed between neighbor cells.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct StepRule {
+    max_climb: u32,
+    max_descent: u32,
+    diagonal: bool,
+}
+
+impl StepRule {
+    /// The puzzle rule: climb at most one level, go down any amount, no
+    /// diagonal moves.
+    fn new() -> StepRule {
+        StepRule {max_climb: 1, max_descent: u32::MAX, diagonal: false}
+    }
+
+    fn allows(&self, from_height: u32, to_height: u32) -> bool {
+        if to_height > from_height {
+            to_height - from_height <= self.max_climb
+        } else {
+            from_height - to_height <= self.max_descent
+        }
+    }
+}
+
 
+#[derive(Clone)]
 struct Node {
     row_index: usize,
     col_index: usize,
@@ -17,46 +39,44 @@ struct Node {
 
 impl Node {
     fn new(row_index: usize, col_index: usize, height_char: char) -> Node {
-        let height = CHARS_INDEXES.iter()
-            .position(|&c| c == height_char).unwrap() as u32;
+        assert!(height_char.is_ascii_lowercase(), "Invalid height: {}", height_char);
         Node {
             row_index,
             col_index,
-            height,
+            height: height_char as u32 - 'a' as u32,
             cost: 0,
             path_length: 0,
         }
     }
 
-    fn clone(&self) -> Node {
-        Node {
-            row_index: self.row_index,
-            col_index: self.col_index,
-            height: self.height,
-            cost: self.cost,
-            path_length: self.path_length,
+    /// Lower bound of the steps to the other node: Manhattan distance, or
+    /// Chebyshev distance with diagonal moves.
+    fn distance_to(&self, other: &Node, diagonal: bool) -> u32 {
+        let row_diff = self.row_index.abs_diff(other.row_index) as u32;
+        let col_diff = self.col_index.abs_diff(other.col_index) as u32;
+        if diagonal {
+            row_diff.max(col_diff)
+        } else {
+            row_diff + col_diff
         }
     }
 
-    fn distance_to(&self, other: &Node) -> u32 {
-        let row_diff = (self.row_index as i32 - other.row_index as i32).abs();
-        let col_diff = (self.col_index as i32 - other.col_index as i32).abs();
-        (row_diff + col_diff) as u32
-    }
-
     fn neighbors(&self, landscape: &Landscape) -> Vec<Node> {
         let mut neighbors = Vec::new();
-        if self.row_index > 0 {
-            neighbors.push(landscape.map[self.row_index - 1][self.col_index].clone());
-        }
-        if self.row_index < landscape.n_rows - 1 {
-            neighbors.push(landscape.map[self.row_index + 1][self.col_index].clone());
-        }
-        if self.col_index > 0 {
-            neighbors.push(landscape.map[self.row_index][self.col_index - 1].clone());
-        }
-        if self.col_index < landscape.n_cols - 1 {
-            neighbors.push(landscape.map[self.row_index][self.col_index + 1].clone());
+        for row_diff in -1..=1_i32 {
+            for col_diff in -1..=1_i32 {
+                if (row_diff == 0 && col_diff == 0)
+                    || (!landscape.rule.diagonal && row_diff != 0 && col_diff != 0) {
+                    continue;
+                }
+                let row_index = self.row_index.checked_add_signed(row_diff as isize);
+                let col_index = self.col_index.checked_add_signed(col_diff as isize);
+                if let (Some(row_index), Some(col_index)) = (row_index, col_index) {
+                    if row_index < landscape.n_rows && col_index < landscape.n_cols {
+                        neighbors.push(landscape.map[row_index][col_index].clone());
+                    }
+                }
+            }
         }
         neighbors
     }
@@ -90,13 +110,17 @@ struct Landscape {
     n_cols: usize,
     start: Node,
     end: Node,
+    rule: StepRule,
 }
 
 impl Landscape {
 
     fn from(filename: &str) -> Landscape {
         let file = File::open(filename).unwrap();
-        let reader = BufReader::new(file);
+        Self::parse(BufReader::new(file))
+    }
+
+    fn parse(reader: impl BufRead) -> Landscape {
         let mut map = Vec::new();
         let mut start = Node::new(0, 0, 'a');
         let mut end = Node::new(0, 0, 'a');
@@ -125,12 +149,16 @@ impl Landscape {
             n_cols,
             start,
             end,
+            rule: StepRule::new(),
         }
     }
 }
 
-fn a_star (landscape: &Landscape) -> u32 {
+/// Shortest path from the start to the end, both included.
+fn a_star(landscape: &Landscape) -> Option<Vec<Node>> {
+    let diagonal = landscape.rule.diagonal;
     let mut visited = vec![vec![false; landscape.n_cols]; landscape.n_rows];
+    let mut came_from: Vec<Vec<Option<Node>>> = vec![vec![None; landscape.n_cols]; landscape.n_rows];
     let mut heap = BinaryHeap::new();
     heap.push(landscape.start.clone());
     while let Some(position) = heap.pop() {
@@ -141,58 +169,187 @@ fn a_star (landscape: &Landscape) -> u32 {
         }
         visited[y][x] = true;
         if position == landscape.end {
-            return position.path_length;
+            let mut path = vec![position];
+            while let Some(previous) = &came_from[path.last().unwrap().row_index][path.last().unwrap().col_index] {
+                path.push(previous.clone());
+            }
+            path.reverse();
+            return Some(path);
         }
 
-        for mut new_position in position.neighbors(&landscape) {
+        for mut new_position in position.neighbors(landscape) {
             let x_new = new_position.col_index;
             let y_new = new_position.row_index;
             if visited[y_new][x_new] {
                 continue;
             }
-            if (position.height + 1) < new_position.height {
+            if !landscape.rule.allows(position.height, new_position.height) {
+                continue;
+            }
+            let path_length = position.path_length + 1;
+            if came_from[y_new][x_new].as_ref().is_some_and(|previous| previous.path_length < path_length) {
                 continue;
             }
-            let distance_to_end = new_position.distance_to(&landscape.end);
-            new_position.path_length = position.path_length + 1;
+            came_from[y_new][x_new] = Some(position.clone());
+            let distance_to_end = new_position.distance_to(&landscape.end, diagonal);
+            new_position.path_length = path_length;
             new_position.cost = new_position.path_length + distance_to_end;
             heap.push(new_position);
         }
     }
-    0
+    None
 }
 
 
-fn part1() {
-    let landscape = Landscape::from("../input.txt");
-    let cost = a_star(&landscape);
-    println!("Part 1: {}", cost);
+/// Map with the path drawn as in the puzzle: each step is an arrow pointing
+/// to the next cell (`/` and `\` for diagonal moves), `E` marks the end.
+fn render_path(landscape: &Landscape, path: &[Node]) -> String {
+    let mut cells = vec![vec!['.'; landscape.n_cols]; landscape.n_rows];
+    for step in path.windows(2) {
+        let (from, to) = (&step[0], &step[1]);
+        let row_diff = to.row_index as i32 - from.row_index as i32;
+        let col_diff = to.col_index as i32 - from.col_index as i32;
+        cells[from.row_index][from.col_index] = match (row_diff, col_diff) {
+            (0, 1) => '>',
+            (0, -1) => '<',
+            (-1, 0) => '^',
+            (1, 0) => 'v',
+            (-1, 1) | (1, -1) => '/',
+            _ => '\\',
+        };
+    }
+    cells[landscape.end.row_index][landscape.end.col_index] = 'E';
+    cells.iter().map(|row| row.iter().collect::<String>() + "\n").collect()
 }
 
 
-fn part2() {
-    let mut fewest_steps = 400;
-    let mut landscape = Landscape::from("../input.txt");
-    for row in &landscape.map {
-        for node in row {
-            if node.height != 0 {
-                continue;
-            }
-            landscape.start = node.clone();
-            let cost = a_star(&landscape);
-            if cost == 0 {
+/// Steps from every cell to the end, `None` where it can not be reached.
+/// Breadth first search from the end, taking the steps backwards.
+fn distances_to_end(landscape: &Landscape) -> Vec<Vec<Option<u32>>> {
+    let mut distances = vec![vec![None; landscape.n_cols]; landscape.n_rows];
+    distances[landscape.end.row_index][landscape.end.col_index] = Some(0);
+    let mut queue = VecDeque::from([landscape.end.clone()]);
+    while let Some(position) = queue.pop_front() {
+        let distance = distances[position.row_index][position.col_index].unwrap();
+        for previous in position.neighbors(landscape) {
+            let seen = &mut distances[previous.row_index][previous.col_index];
+            if seen.is_some() || !landscape.rule.allows(previous.height, position.height) {
                 continue;
             }
-            if cost < fewest_steps {
-                fewest_steps = cost;
+            *seen = Some(distance + 1);
+            queue.push_back(previous);
+        }
+    }
+    distances
+}
+
+
+/// Fewest steps to the end from any cell accepted by `is_start`.
+fn fewest_steps_from(landscape: &Landscape, is_start: impl Fn(&Node) -> bool) -> Option<u32> {
+    let distances = distances_to_end(landscape);
+    landscape.map.iter().flatten()
+        .filter(|node| is_start(node))
+        .filter_map(|node| distances[node.row_index][node.col_index])
+        .min()
+}
+
+
+fn print_distances(landscape: &Landscape) {
+    for row in distances_to_end(landscape) {
+        let cells: Vec<String> = row.iter()
+            .map(|distance| distance.map_or("  .".to_string(), |distance| format!("{:>3}", distance)))
+            .collect();
+        println!("{}", cells.join(" "));
+    }
+}
+
+
+fn option(args: &[String], name: &str) -> Option<u32> {
+    let index = args.iter().position(|arg| arg == name)?;
+    Some(args[index + 1].parse().unwrap())
+}
+
+
+/// Landscape with the rule of the `--max-climb`, `--max-descent` and
+/// `--diagonal` options.
+fn load_landscape(args: &[String]) -> Landscape {
+    let mut landscape = Landscape::from("../input.txt");
+    let default = StepRule::new();
+    landscape.rule = StepRule {
+        max_climb: option(args, "--max-climb").unwrap_or(default.max_climb),
+        max_descent: option(args, "--max-descent").unwrap_or(default.max_descent),
+        diagonal: args.iter().any(|arg| arg == "--diagonal"),
+    };
+    landscape
+}
+
+
+fn part1(args: &[String]) {
+    let landscape = load_landscape(args);
+    match a_star(&landscape) {
+        Some(path) => {
+            println!("Part 1: {}", path.len() - 1);
+            if args.iter().any(|arg| arg == "--path") {
+                print!("{}", render_path(&landscape, &path));
             }
         }
+        None => println!("Part 1: the end can not be reached"),
+    }
+}
+
+
+/// Starts from any cell up to `--max-start-height` (0, the `a` cells, by
+/// default).
+fn part2(args: &[String]) {
+    let max_height = option(args, "--max-start-height").unwrap_or(0);
+    let landscape = load_landscape(args);
+    match fewest_steps_from(&landscape, |node| node.height <= max_height) {
+        Some(fewest_steps) => println!("Part 2: {}", fewest_steps),
+        None => println!("Part 2: the end can not be reached"),
     }
-    println!("Part 2: {}", fewest_steps);
 }
 
 
 fn main() {
-    part1();
-    part2();
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--distances") {
+        print_distances(&load_landscape(&args));
+        return;
+    }
+    part1(&args);
+    part2(&args);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{a_star, fewest_steps_from, render_path, Landscape, StepRule};
+
+    const EXAMPLE: &str = "Sabqponm\nabcryxxl\naccszExk\nacctuvwj\nabdefghi\n";
+
+    #[test]
+    fn example() {
+        let landscape = Landscape::parse(EXAMPLE.as_bytes());
+        let path = a_star(&landscape).unwrap();
+        assert_eq!(path.len() - 1, 31);
+        // Another of the shortest paths than the one of the puzzle.
+        let map = render_path(&landscape, &path);
+        assert_eq!(map, ">>vv<<<<\n..vvv<<^\n..vv>E^^\n..v>>>^^\n..>>>>>^\n");
+        assert_eq!(map.chars().filter(|c| "<>^v".contains(*c)).count(), 31);
+        assert_eq!(fewest_steps_from(&landscape, |node| node.height == 0), Some(29));
+        assert_eq!(fewest_steps_from(&landscape, |node| node == &landscape.start), Some(31));
+    }
+
+    #[test]
+    fn rules() {
+        let mut landscape = Landscape::parse(EXAMPLE.as_bytes());
+        landscape.rule = StepRule {max_climb: 1, max_descent: u32::MAX, diagonal: true};
+        let steps = a_star(&landscape).unwrap().len() as u32 - 1;
+        assert!(steps < 31);
+        assert_eq!(fewest_steps_from(&landscape, |node| node == &landscape.start), Some(steps));
+        landscape.rule = StepRule {max_climb: 25, max_descent: 0, diagonal: false};
+        assert_eq!(a_star(&landscape).unwrap().len(), 8);
+        landscape.rule = StepRule {max_climb: 0, max_descent: 0, diagonal: false};
+        assert!(a_star(&landscape).is_none());
+    }
 }
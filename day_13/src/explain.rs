@@ -0,0 +1,176 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::packet::Packet;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    fn name(&self) -> &'static str {
+        match self {
+            Side::Left => "left",
+            Side::Right => "right",
+        }
+    }
+
+    /// End of the sentences deciding the order when this side is smaller.
+    fn verdict(&self) -> &'static str {
+        match self {
+            Side::Left => "so inputs are in the right order",
+            Side::Right => "so inputs are not in the right order",
+        }
+    }
+}
+
+
+/// Step of a comparison, with the steps it took nested in `Compare`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Compare {left: Packet, right: Packet, steps: Vec<Step>},
+    /// The integer of `side` is turned into the list `packet`.
+    Convert {side: Side, packet: Packet},
+    /// Two integers differ, `side` has the smaller one.
+    Smaller(Side),
+    /// `side` has fewer items than the other list.
+    RanOut(Side),
+}
+
+impl Step {
+    fn render(&self, depth: usize, f: &mut fmt::Formatter) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+        match self {
+            Step::Compare {left, right, steps} => {
+                writeln!(f, "{}- Compare {} vs {}", indent, left, right)?;
+                for step in steps {
+                    step.render(depth + 1, f)?;
+                }
+                Ok(())
+            }
+            Step::Convert {side, packet} => {
+                writeln!(f, "{}- Mixed types; convert {} to {} and retry comparison", indent, side.name(), packet)
+            }
+            Step::Smaller(side) => {
+                let name = if *side == Side::Left { "Left" } else { "Right" };
+                writeln!(f, "{}- {} side is smaller, {}", indent, name, side.verdict())
+            }
+            Step::RanOut(side) => {
+                let name = if *side == Side::Left { "Left" } else { "Right" };
+                writeln!(f, "{}- {} side ran out of items, {}", indent, name, side.verdict())
+            }
+        }
+    }
+}
+
+/// Nested trace as in the puzzle statement.
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.render(0, f)
+    }
+}
+
+
+/// Compares two packets like `Ord`, also returning every step taken.
+pub fn explain(left: &Packet, right: &Packet) -> (Ordering, Step) {
+    let mut steps = Vec::new();
+    let ordering = match (left, right) {
+        (Packet::Int(left_value), Packet::Int(right_value)) => {
+            let ordering = left_value.cmp(right_value);
+            match ordering {
+                Ordering::Less => steps.push(Step::Smaller(Side::Left)),
+                Ordering::Greater => steps.push(Step::Smaller(Side::Right)),
+                Ordering::Equal => {}
+            }
+            ordering
+        }
+        (Packet::List(left_packets), Packet::List(right_packets)) => {
+            let mut ordering = Ordering::Equal;
+            for (left_packet, right_packet) in left_packets.iter().zip(right_packets.iter()) {
+                let (item_ordering, step) = explain(left_packet, right_packet);
+                steps.push(step);
+                ordering = item_ordering;
+                if ordering != Ordering::Equal {
+                    break;
+                }
+            }
+            if ordering == Ordering::Equal {
+                ordering = left_packets.len().cmp(&right_packets.len());
+                match ordering {
+                    Ordering::Less => steps.push(Step::RanOut(Side::Left)),
+                    Ordering::Greater => steps.push(Step::RanOut(Side::Right)),
+                    Ordering::Equal => {}
+                }
+            }
+            ordering
+        }
+        (Packet::Int(value), Packet::List(_)) => {
+            let packet = Packet::List(vec![Packet::Int(*value)]);
+            steps.push(Step::Convert {side: Side::Left, packet: packet.clone()});
+            let (ordering, step) = explain(&packet, right);
+            steps.push(step);
+            ordering
+        }
+        (Packet::List(_), Packet::Int(value)) => {
+            let packet = Packet::List(vec![Packet::Int(*value)]);
+            steps.push(Step::Convert {side: Side::Right, packet: packet.clone()});
+            let (ordering, step) = explain(left, &packet);
+            steps.push(step);
+            ordering
+        }
+    };
+    (ordering, Step::Compare {left: left.clone(), right: right.clone(), steps})
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::explain;
+    use super::super::packet::Packet;
+
+    fn trace(left: &str, right: &str) -> String {
+        let (left, right) = (Packet::parse(left).unwrap(), Packet::parse(right).unwrap());
+        let (ordering, step) = explain(&left, &right);
+        assert_eq!(ordering, left.cmp(&right));
+        step.to_string()
+    }
+
+    #[test]
+    fn puzzle_traces() {
+        assert_eq!(trace("[[1],[2,3,4]]", "[[1],4]"), "\
+- Compare [[1],[2,3,4]] vs [[1],4]
+  - Compare [1] vs [1]
+    - Compare 1 vs 1
+  - Compare [2,3,4] vs 4
+    - Mixed types; convert right to [4] and retry comparison
+    - Compare [2,3,4] vs [4]
+      - Compare 2 vs 4
+        - Left side is smaller, so inputs are in the right order
+");
+        assert_eq!(trace("[9]", "[[8,7,6]]"), "\
+- Compare [9] vs [[8,7,6]]
+  - Compare 9 vs [8,7,6]
+    - Mixed types; convert left to [9] and retry comparison
+    - Compare [9] vs [8,7,6]
+      - Compare 9 vs 8
+        - Right side is smaller, so inputs are not in the right order
+");
+        assert_eq!(trace("[[4,4],4,4]", "[[4,4],4,4,4]"), "\
+- Compare [[4,4],4,4] vs [[4,4],4,4,4]
+  - Compare [4,4] vs [4,4]
+    - Compare 4 vs 4
+    - Compare 4 vs 4
+  - Compare 4 vs 4
+  - Compare 4 vs 4
+  - Left side ran out of items, so inputs are in the right order
+");
+        assert_eq!(trace("[[[]]]", "[[]]"), "\
+- Compare [[[]]] vs [[]]
+  - Compare [[]] vs []
+    - Right side ran out of items, so inputs are not in the right order
+");
+    }
+}
@@ -1,297 +1,162 @@
+mod explain;
+mod packet;
+
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
+use serde_json::Value;
 
-trait Element {
-    fn is_smaller(&self, other: Box<dyn Element>) -> bool;
-    fn is_equal(&self, other: Box<dyn Element>) -> bool;
-    fn len(&self) -> Option<usize>;
-    fn element(&self, index: usize) -> Option<Box<dyn Element>>;
-    fn is_leaf(&self) -> bool;
-    fn value(&self) -> Option<u32>;
-    fn clone(&self) -> Box<dyn Element>;
-    fn print(&self) -> String;
-}
-
-struct ListOfElements {
-    elements: Vec<Box<dyn Element>>,
-}
+use packet::Packet;
 
-impl ListOfElements {
-    fn new(elements: Vec<Box<dyn Element>>) -> Self {
-        Self { elements }
-    }
 
-    fn from_leaf(leaf_value: u32) -> Self {
-        Self {
-            elements: vec![Box::new(ElementLeaf {value: leaf_value})],
-        }
-    }
-
-    fn from(text_line: &str) -> Self {
-        let mut elements = Self::new(Vec::new());
-        let mut depth = 0;
-        let mut start_index = 1;
-        while start_index < (text_line.len() - 1) {
-            let mut ch = text_line.chars().nth(start_index).unwrap();
-            if ch == '[' {
-                depth += 1;
-                let mut end_index = start_index;
-                while depth > 0 {
-                    end_index += 1;
-                    let ch = text_line.chars().nth(end_index).unwrap();
-                    if ch == '[' {
-                        depth += 1;
-                    } else if ch == ']' {
-                        depth -= 1;
-                    }
-                }
-                elements.elements.push(Box::new(ListOfElements::from(&text_line[start_index..end_index+1])));
-                start_index = end_index;
-            } else if ch.is_ascii_digit() {
-                // Careful: there may be more than one digit together
-                let mut number = String::new();
-                while ch.is_ascii_digit() {
-                    number.push(ch);
-                    start_index += 1;
-                    ch = text_line.chars().nth(start_index).unwrap();
-                }
-                elements.elements.push(Box::new(ElementLeaf {value: number.parse::<u32>().unwrap()}));
-                start_index -= 1;
-            } else {
-            }
-            start_index += 1;
-        }
-        elements
-    }
+fn read_file() -> BufReader<File> {
+    let file = File::open("../input.txt").unwrap();
+    BufReader::new(file)
 }
 
-impl Element for ListOfElements {
-    fn len(&self) -> Option<usize> {
-        Some(self.elements.len())
-    }
-
-    fn element(&self, index: usize) -> Option<Box<dyn Element>> {
-        if index >= self.elements.len() {
-            return None
-        }
-        Some(self.elements[index].clone())
-    }
-
-    fn is_leaf(&self) -> bool {
-        false
-    }
-
-    fn value(&self) -> Option<u32> {
-        None
-    }
-
-    fn is_smaller(&self, other: Box<dyn Element>) -> bool {
-        if other.is_leaf() {
-            return self.is_smaller(Box::new(Self::from_leaf(other.value().unwrap())));
-        }
-        let self_len = self.len().unwrap();
-        let other_len = other.len().unwrap();
-        let mut index = 0;
-        while index < std::cmp::min(self_len, other_len) {
-            let self_el = self.element(index).unwrap();
-            let other_el = other.element(index).unwrap();
-            if self_el.is_equal(other_el.clone()) {
-                index += 1;
-                continue;
-            }
-            return self_el.is_smaller(other_el);
-        }
-        if self_len == other_len {
-            panic!("DRAW, not possible");
-        }
-        return self_len < other_len;
-    }
-
-    fn is_equal(&self, other: Box<dyn Element>) -> bool {
-        if other.is_leaf() {
-            let aux = ListOfElements::from_leaf(other.value().unwrap());
-            return self.is_equal(Box::new(aux));
-        }
-
-        let self_len = self.len().unwrap();
-        let other_len = other.len().unwrap();
-        if self_len != other_len {
-            return false;
-        }
-        for i in 0..self_len {
-            if !self.element(i).unwrap().is_equal(other.element(i).unwrap()) {
-                return false;
-            }
-        }
-        true
-    }
-
-    fn clone(&self) -> Box<dyn Element> {
-        let mut new = Self::new(Vec::new());
-        for index in 0..self.elements.len() {
-            new.elements.push(self.elements[index].clone())
-        }
-        Box::new(new)
-    }
 
-    fn print(&self) -> String {
-        let mut result = String::new();
-        result.push('[');
-        for index in 0..self.elements.len() {
-            result.push_str(&self.elements[index].print());
-            if index < self.elements.len() - 1 {
-                result.push(',');
-            }
-        }
-        result.push(']');
-        result
-    }
+/// Every packet of the input, skipping the blank lines between pairs.
+fn read_packets() -> Vec<Packet> {
+    read_file().lines()
+        .map(|line| line.unwrap())
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(index, line)| match Packet::parse(&line) {
+            Ok(packet) => packet,
+            Err(error) => panic!("Invalid packet at line {}, {}", index + 1, error),
+        })
+        .collect()
 }
 
-struct ElementLeaf {
-    value: u32
-}
-
-impl Element for ElementLeaf {
-    fn len(&self) -> Option<usize> {
-        None
-    }
 
-    fn clone(&self) -> Box<dyn Element> {
-        Box::new(Self {value: self.value})
-    }
-
-    fn element(&self, index: usize) -> Option<Box<dyn Element>> {
-        None
-    }
-
-    fn is_leaf(&self) -> bool {
-        true
-    }
-
-    fn value(&self) -> Option<u32> {
-        Some(self.value)
-    }
-
-    fn is_smaller(&self, other: Box<dyn Element>) -> bool {
-        if other.is_leaf() {
-            self.value().unwrap() < other.value().unwrap()
-        } else {
-            let aux = ListOfElements::from_leaf(self.value);
-            aux.is_smaller(other)
-        }
-    }
-
-    fn is_equal(&self, other: Box<dyn Element>) -> bool {
-        if other.is_leaf() {
-            self.value().unwrap() == other.value().unwrap()
-        } else {
-            let aux = ListOfElements::from_leaf(self.value);
-            aux.is_equal(other)
-        }
-    }
-
-    fn print(&self) -> String {
-        self.value.to_string()
-    }
+fn part1() {
+    let packets = read_packets();
+    let result: usize = packets.chunks(2)
+        .enumerate()
+        .filter(|(_, pair)| pair[0] < pair[1])
+        .map(|(index, _)| index + 1)
+        .sum();
+    println!("Part 1: {}", result);
 }
 
-impl std::cmp::PartialEq for ListOfElements {
-    fn eq(&self, other: &Self) -> bool {
-        self.is_equal(other.clone())
-    }
 
-    fn ne(&self, other: &Self) -> bool {
-        !self.is_equal(other.clone())
+/// Divider packets of the `--divider` options, `[[2]]` and `[[6]]` by
+/// default.
+fn dividers(args: &[String]) -> Vec<Packet> {
+    let mut dividers: Vec<Packet> = args.windows(2)
+        .filter(|pair| pair[0] == "--divider")
+        .map(|pair| Packet::parse(&pair[1]).unwrap_or_else(|error| panic!("Invalid divider {}, {}", pair[1], error)))
+        .collect();
+    if dividers.is_empty() {
+        dividers = vec![Packet::parse("[[2]]").unwrap(), Packet::parse("[[6]]").unwrap()];
     }
+    dividers
 }
 
 
-impl std::cmp::Eq for ListOfElements {}
+/// Product of the positions of the dividers after sorting them with the
+/// packets. Dividers are tagged, as they can be equal to other packets.
+fn decoder_key(packets: Vec<Packet>, dividers: Vec<Packet>) -> usize {
+    let mut all_packets: Vec<(Packet, bool)> = packets.into_iter().map(|packet| (packet, false)).collect();
+    all_packets.extend(dividers.into_iter().map(|divider| (divider, true)));
+    all_packets.sort_by(|(left, _), (right, _)| left.cmp(right));
+    all_packets.iter()
+        .enumerate()
+        .filter(|(_, (_, is_divider))| *is_divider)
+        .map(|(index, _)| index + 1)
+        .product()
+}
 
 
-impl std::cmp::PartialOrd for ListOfElements {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        if self.is_equal(other.clone()) {
-            Some(std::cmp::Ordering::Equal)
-        } else if self.is_smaller(other.clone()) {
-            Some(std::cmp::Ordering::Less)
-        } else {
-            Some(std::cmp::Ordering::Greater)
-        }
-    }
+fn part2(args: &[String]) {
+    let result = decoder_key(read_packets(), dividers(args));
+    println!("Part 2: {}", result);
 }
 
 
-impl std::cmp::Ord for ListOfElements {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        if self.is_smaller(other.clone()) {
-            std::cmp::Ordering::Less
-        } else if self.is_equal(other.clone()) {
-            std::cmp::Ordering::Equal
-        } else {
-            std::cmp::Ordering::Greater
-        }
+/// Sorts the packets of a JSON Lines file, printing each one with its
+/// position after sorting and its line in the file.
+fn sort_json_lines(path: &str) {
+    let text = std::fs::read_to_string(path).unwrap();
+    let mut packets: Vec<(usize, Packet)> = Vec::new();
+    for (index, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let value: Value = serde_json::from_str(line)
+            .unwrap_or_else(|error| panic!("{}:{}: invalid JSON, {}", path, index + 1, error));
+        let packet = Packet::try_from(&value)
+            .unwrap_or_else(|error| panic!("{}:{}: invalid packet {}", path, index + 1, error));
+        packets.push((index + 1, packet));
+    }
+    packets.sort_by(|(_, left), (_, right)| left.cmp(right));
+    for (position, (line, packet)) in packets.iter().enumerate() {
+        println!("{:>5} {} (line {})", position + 1, Value::from(packet), line);
     }
 }
 
 
-fn read_file() -> BufReader<File> {
-    let file = File::open("../input.txt").unwrap();
-    BufReader::new(file)
+/// Prints the comparison trace of two packets, as in the puzzle.
+fn print_explanation(left: &Packet, right: &Packet) {
+    let (ordering, step) = explain::explain(left, right);
+    print!("{}", step);
+    if ordering == std::cmp::Ordering::Equal {
+        println!("Inputs are equal");
+    }
 }
 
 
-fn part1() {
-    let reader = read_file();
-    let mut pair: Vec<ListOfElements> = Vec::new();
-    let mut result = 0;
-    for (li, line) in reader.lines().enumerate() {
-        let line = line.unwrap();
-        if line.is_empty() {
-            let pc = pair[1].clone();
-            if pair[0].is_smaller(pc) {
-                result += (li + 1)/3 ;
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--explain") {
+        let (Some(left), Some(right)) = (args.get(index + 1), args.get(index + 2)) else {
+            println!("Missing packets, use --explain LEFT RIGHT");
+            return;
+        };
+        match (Packet::parse(left), Packet::parse(right)) {
+            (Ok(left), Ok(right)) => print_explanation(&left, &right),
+            (Err(error), _) => println!("Invalid packet {}, {}", left, error),
+            (_, Err(error)) => println!("Invalid packet {}, {}", right, error),
+        }
+        return;
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--explain-pair") {
+        let packets = read_packets();
+        let n_pairs = packets.len() / 2;
+        let Some(text) = args.get(index + 1) else {
+            println!("Missing pair, the input has pairs 1 to {}", n_pairs);
+            return;
+        };
+        let pair: usize = match text.parse() {
+            Ok(pair) if (1..=n_pairs).contains(&pair) => pair,
+            _ => {
+                println!("Invalid pair {}, the input has pairs 1 to {}", text, n_pairs);
+                return;
             }
-            pair.clear();
-            continue;
-        }
-        let elements = ListOfElements::from(&line);
-        assert_eq!(elements.print(), line);
-        pair.push(elements);
+        };
+        println!("== Pair {} ==", pair);
+        print_explanation(&packets[2 * pair - 2], &packets[2 * pair - 1]);
+        return;
     }
-    println!("Part 1: {}", result);
+    if let Some(index) = args.iter().position(|arg| arg == "--sort") {
+        sort_json_lines(&args[index + 1]);
+        return;
+    }
+    part1();
+    part2(&args);
 }
 
 
-fn part2() {
-    let reader = read_file();
-    let mut all_elements: Vec<ListOfElements> = Vec::new();
-    all_elements.push(ListOfElements::from("[[2]]"));
-    all_elements.push(ListOfElements::from("[[6]]"));
+#[cfg(test)]
+mod tests {
+    use super::decoder_key;
+    use super::packet::Packet;
 
-    for line in reader.lines() {
-        let line = line.unwrap();
-        if line.is_empty() {
-            continue;
-        }
-        let elements = ListOfElements::from(&line);
-        all_elements.push(elements);
+    fn packets(texts: &[&str]) -> Vec<Packet> {
+        texts.iter().map(|text| Packet::parse(text).unwrap()).collect()
     }
-    all_elements.sort();
-    let mut result = 1;
-    for index in 0..all_elements.len() {
-        let current = all_elements[index].clone();
-        if current.is_equal(Box::new(ListOfElements::from("[[2]]"))) | current.is_equal(Box::new(ListOfElements::from("[[6]]"))) {
-            result *= index + 1;
-        }
-    }
-    println!("Part 2: {}", result);
-}
-
 
-fn main() {
-    part1();
-    part2();
+    #[test]
+    fn equal_dividers() {
+        assert_eq!(decoder_key(packets(&["[1]", "[3]", "[7]"]), packets(&["[[2]]", "[[6]]"])), 2 * 4);
+        assert_eq!(decoder_key(packets(&["[1]", "[2]", "[7]"]), packets(&["[[2]]", "[[6]]"])), 3 * 4);
+        assert_eq!(decoder_key(packets(&["[1]", "[7]"]), packets(&["[[2]]", "[2]"])), 2 * 3);
+    }
 }
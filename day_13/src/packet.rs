@@ -0,0 +1,252 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use serde_json::Value;
+
+
+/// Packet of the distress signal: an integer or a list of packets.
+///
+/// Packets are ordered with the puzzle rules, so an integer compares as a
+/// list holding only that integer and `[[2]]`, `[2]` and `2` are equal.
+#[derive(Debug, Clone)]
+pub enum Packet {
+    Int(u32),
+    List(Vec<Packet>),
+}
+
+impl Packet {
+    /// Parses the text of a packet, which must be a list.
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {bytes: text.as_bytes(), position: 0};
+        if parser.peek() != Some(b'[') {
+            return Err(parser.error("expected `[`"));
+        }
+        let packet = parser.packet()?;
+        if parser.position != text.len() {
+            return Err(parser.error("unexpected text after the packet"));
+        }
+        Ok(packet)
+    }
+}
+
+impl Ord for Packet {
+    fn cmp(&self, other: &Packet) -> Ordering {
+        match (self, other) {
+            (Packet::Int(left), Packet::Int(right)) => left.cmp(right),
+            (Packet::List(left), Packet::List(right)) => left.iter().cmp(right.iter()),
+            (Packet::Int(left), Packet::List(right)) => std::slice::from_ref(&Packet::Int(*left)).iter().cmp(right.iter()),
+            (Packet::List(left), Packet::Int(right)) => left.iter().cmp(std::slice::from_ref(&Packet::Int(*right)).iter()),
+        }
+    }
+}
+
+impl PartialOrd for Packet {
+    fn partial_cmp(&self, other: &Packet) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Packet {
+    fn eq(&self, other: &Packet) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Packet {}
+
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Packet::Int(value) => write!(f, "{}", value),
+            Packet::List(packets) => {
+                write!(f, "[")?;
+                for (index, packet) in packets.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", packet)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+
+/// Error of the parser, `position` is the byte offset in the text.
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "position {}: {}", self.position, self.message)
+    }
+}
+
+
+impl From<&Packet> for Value {
+    fn from(packet: &Packet) -> Value {
+        match packet {
+            Packet::Int(value) => Value::from(*value),
+            Packet::List(packets) => Value::Array(packets.iter().map(Value::from).collect()),
+        }
+    }
+}
+
+impl TryFrom<&Value> for Packet {
+    type Error = JsonError;
+
+    /// Accepts arrays and integers that fit in a `u32`.
+    fn try_from(value: &Value) -> Result<Packet, JsonError> {
+        from_json(value, String::new())
+    }
+}
+
+fn from_json(value: &Value, pointer: String) -> Result<Packet, JsonError> {
+    match value {
+        Value::Array(values) => values.iter()
+            .enumerate()
+            .map(|(index, value)| from_json(value, format!("{}/{}", pointer, index)))
+            .collect::<Result<_, _>>()
+            .map(Packet::List),
+        Value::Number(number) => number.as_u64()
+            .and_then(|number| u32::try_from(number).ok())
+            .map(Packet::Int)
+            .ok_or_else(|| JsonError {pointer, message: format!("{} is not an integer in the u32 range", number)}),
+        _ => Err(JsonError {pointer, message: format!("expected an array or an integer, found {}", value)}),
+    }
+}
+
+
+/// Value that is not a packet, `pointer` is its JSON pointer (empty for the
+/// whole document).
+#[derive(Debug, PartialEq)]
+pub struct JsonError {
+    pub pointer: String,
+    pub message: String,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at `{}`: {}", self.pointer, self.message)
+    }
+}
+
+
+/// Recursive descent parser, reading each byte once.
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {position: self.position, message: message.to_string()}
+    }
+
+    fn packet(&mut self) -> Result<Packet, ParseError> {
+        match self.peek() {
+            Some(b'[') => self.list(),
+            Some(byte) if byte.is_ascii_digit() => self.int(),
+            Some(_) => Err(self.error("expected an integer or `[`")),
+            None => Err(self.error("unexpected end of packet")),
+        }
+    }
+
+    fn list(&mut self) -> Result<Packet, ParseError> {
+        self.position += 1;
+        let mut packets = Vec::new();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Packet::List(packets));
+        }
+        loop {
+            packets.push(self.packet()?);
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Packet::List(packets));
+                }
+                Some(_) => return Err(self.error("expected `,` or `]`")),
+                None => return Err(self.error("unexpected end of packet")),
+            }
+        }
+    }
+
+    fn int(&mut self) -> Result<Packet, ParseError> {
+        let start = self.position;
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.position += 1;
+        }
+        let digits = std::str::from_utf8(&self.bytes[start..self.position]).unwrap();
+        digits.parse().map(Packet::Int)
+            .map_err(|_| ParseError {position: start, message: format!("integer {} out of range", digits)})
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{JsonError, Packet, ParseError};
+
+    fn packet(text: &str) -> Packet {
+        Packet::parse(text).unwrap()
+    }
+
+    #[test]
+    fn ordering() {
+        let pairs = [
+            ("[1,1,3,1,1]", "[1,1,5,1,1]", true),
+            ("[[1],[2,3,4]]", "[[1],4]", true),
+            ("[9]", "[[8,7,6]]", false),
+            ("[[4,4],4,4]", "[[4,4],4,4,4]", true),
+            ("[7,7,7,7]", "[7,7,7]", false),
+            ("[]", "[3]", true),
+            ("[[[]]]", "[[]]", false),
+            ("[1,[2,[3,[4,[5,6,7]]]],8,9]", "[1,[2,[3,[4,[5,6,0]]]],8,9]", false),
+        ];
+        for (left, right, ordered) in pairs {
+            assert_eq!(packet(left) < packet(right), ordered, "{} vs {}", left, right);
+        }
+        assert_eq!(packet("[[2]]"), packet("[2]"));
+    }
+
+    #[test]
+    fn round_trip() {
+        for text in ["[]", "[[]]", "[10,[2,[]],300]"] {
+            assert_eq!(packet(text).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Packet::parse("[1,,2]").unwrap_err(), ParseError {position: 3, message: "expected an integer or `[`".to_string()});
+        assert_eq!(Packet::parse("[1,2").unwrap_err().position, 4);
+        assert_eq!(Packet::parse("[1 2]").unwrap_err().position, 2);
+        assert_eq!(Packet::parse("[1]]").unwrap_err().position, 3);
+        assert_eq!(Packet::parse("7").unwrap_err().position, 0);
+        assert_eq!(Packet::parse("[99999999999]").unwrap_err().position, 1);
+    }
+
+    #[test]
+    fn json() {
+        let value = json!([1, [2, []], 30]);
+        let packet = Packet::try_from(&value).unwrap();
+        assert_eq!(packet.to_string(), "[1,[2,[]],30]");
+        assert_eq!(Value::from(&packet), value);
+        assert_eq!(Packet::try_from(&json!([1, [2.5]])).unwrap_err(),
+                   JsonError {pointer: "/1/0".to_string(), message: "2.5 is not an integer in the u32 range".to_string()});
+        assert_eq!(Packet::try_from(&json!([[-1]])).unwrap_err().pointer, "/0/0");
+        assert_eq!(Packet::try_from(&json!([{"a": 1}])).unwrap_err().pointer, "/0");
+    }
+}